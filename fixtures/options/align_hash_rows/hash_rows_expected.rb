@@ -0,0 +1,12 @@
+USERS = [
+  {id: 1,   name: "Alice", admin: true},
+  {id: 20,  name: "Bob",   admin: false},
+  {id: 300, name: "Carol", admin: false}
+]
+
+MIXED = [
+  {a: 1, b: 2},
+  {a: 10, c: 3}
+]
+
+ONE_LINE = [{a: 1, b: 2}, {a: 10, b: 3}]
@@ -0,0 +1,12 @@
+#: (Integer) -> String
+def label(n)
+  n.to_s
+end
+
+
+#: (
+#:   String name
+#: ) -> Symbol
+def key(name)
+  name.to_sym
+end
@@ -0,0 +1,21 @@
+def kind(node)
+  case node.type
+  when :def, :defs
+    :method
+    # calls
+  when :send
+    case node.method_name
+    when :new
+      :constructor
+    else
+      :call
+    end
+  end
+end
+
+case
+  when ready?
+    go
+  else
+    wait
+end
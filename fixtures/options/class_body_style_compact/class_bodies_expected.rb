@@ -0,0 +1,41 @@
+module Empty; end
+
+class Blank < StandardError; end
+
+module Single; VERSION = "1.0"; end
+
+class Point; attr_reader :x; end
+
+class Expanded; end
+
+module AlsoExpanded; VERSION = "2.0"; end
+
+module Errors
+  class NotFound < StandardError; end
+
+  class Timeout < StandardError; end
+
+  def self.all
+    [NotFound, Timeout]
+  end
+end
+
+class Documented
+  # The answer
+  ANSWER = 42
+end
+
+class Pair
+  attr_reader :left
+  attr_reader :right
+end
+
+class Loader
+  def load
+    read
+  end
+end
+
+class Notice
+  MESSAGE = "This notice is shown to every user who signs in before the end of the current billing peri"
+end
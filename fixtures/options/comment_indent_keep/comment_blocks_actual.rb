@@ -0,0 +1,11 @@
+def process
+      # misindented comment
+  step_one
+# another one
+  #   sub-point
+    # indented further
+  step_two
+    # deeper first line
+  # shallower second line
+  finish
+end
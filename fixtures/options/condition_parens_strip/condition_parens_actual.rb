@@ -0,0 +1,49 @@
+if (x > 0)
+  positive
+elsif (x.zero?)
+  zero
+end
+
+unless (valid?)
+  raise(ArgumentError)
+end
+
+while (queue.any?)
+  queue.pop
+end
+
+until (done)
+  work
+end
+
+if (!found && retries < 3)
+  retry_later
+end
+
+if (line = gets)
+  puts(line)
+end
+
+while (node = node.next)
+  visit(node)
+end
+
+if (load_config rescue nil)
+  configure
+end
+
+if ((match = pattern.match(text)) && match[1])
+  use(match)
+end
+
+while (!(line = gets).nil?)
+  puts(line)
+end
+
+unless (retries += 1) > 3
+  retry_later
+end
+
+if (items.include?(item) && !item.nil?)
+  add(item)
+end
@@ -0,0 +1,22 @@
+class Widget
+  def call
+    render(
+        title,
+        body
+    )
+
+    things = [
+        first,
+        second
+    ]
+
+    options = {
+        a: 1,
+        b: 2
+    }
+
+    things
+        .map(&:name)
+        .uniq
+  end
+end
@@ -0,0 +1,21 @@
+port = begin
+  Integer(ENV["PORT"])
+rescue
+  3000
+end
+
+begin
+  File.delete(path)
+rescue
+  nil
+end
+
+def parse(input)
+  begin
+    JSON.parse(input)
+  rescue
+    nil
+  end
+end
+
+total = count + (fetch rescue 0)
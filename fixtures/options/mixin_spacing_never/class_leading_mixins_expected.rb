@@ -0,0 +1,21 @@
+class WithBlankLine
+  include Comparable
+  extend Forwardable
+
+  def <=>(other)
+    name <=> other.name
+  end
+end
+
+class WithoutBlankLine
+  include Comparable
+  prepend Logging
+end
+
+module Helpers
+  extend ActiveSupport::Concern
+
+  included do
+    before_action :authenticate
+  end
+end
@@ -0,0 +1,15 @@
+require "set"
+# rubyfmt:sort-keys
+DEFAULTS = {
+  verbose: false,
+  color: true
+}
+
+ROWS = [
+  {id: 1, name: "a"},
+  {id: 10, name: "b"}
+]
+
+if (ROWS.empty?)
+  port = Integer(ENV["PORT"]) rescue 3000
+end
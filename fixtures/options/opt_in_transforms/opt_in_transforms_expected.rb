@@ -0,0 +1,20 @@
+require "set"
+
+# rubyfmt:sort-keys
+DEFAULTS = {
+  color: true,
+  verbose: false
+}
+
+ROWS = [
+  {id: 1,  name: "a"},
+  {id: 10, name: "b"}
+]
+
+if ROWS.empty?
+  port = begin
+    Integer(ENV["PORT"])
+  rescue
+    3000
+  end
+end
@@ -0,0 +1,15 @@
+class Greeter
+      def greet(name)
+    puts 'Hello, ' + name
+        message = %q(it's "quoted")
+    other = %Q{#{name}   says hi}
+  escaped = 'single \'escaped\' \n'
+      "double #{ name.upcase }"
+  end
+
+  def symbols(name)
+    plain = :'foo bar'
+     interpolated = :"hi #{ name }"
+    [plain,interpolated, "#@name and #{  @other}"]
+  end
+end
@@ -0,0 +1,48 @@
+# rubyfmt:sort-keys
+DEFAULTS = {
+  timeout: 30, # seconds
+  # set by the deploy
+  region: "us-east-1",
+  verbose: false,
+  color: true # always on
+}
+
+ROUTES = {
+  show: "/posts/:id",
+  index: "/posts",
+  create: "/posts"
+}
+
+class Settings
+  # rubyfmt:sort-keys
+  LIMITS = {
+    "uploads" => 10,
+    "downloads" => 5
+  }
+end
+
+# rubyfmt:sort-keys
+OVERRIDES = {
+  b: 1,
+  a: 2,
+  a: 1,
+  # stays at the bottom
+}
+
+# Values that span lines are left where they are
+# rubyfmt:sort-keys
+MESSAGES = {
+  welcome: <<~TEXT,
+    Hello
+  TEXT
+  bye: "Bye"
+}
+
+# rubyfmt:sort-keys
+SIZES = {
+  small: [
+    1,
+    2
+  ],
+  big: 3
+}
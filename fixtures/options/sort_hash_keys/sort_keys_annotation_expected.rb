@@ -0,0 +1,50 @@
+# rubyfmt:sort-keys
+DEFAULTS = {
+  # always on
+  color: true,
+  # set by the deploy
+  region: "us-east-1",
+  # seconds
+  timeout: 30,
+  verbose: false
+}
+
+ROUTES = {
+  show: "/posts/:id",
+  index: "/posts",
+  create: "/posts"
+}
+
+class Settings
+  # rubyfmt:sort-keys
+  LIMITS = {
+    "downloads" => 5,
+    "uploads" => 10
+  }
+end
+
+# rubyfmt:sort-keys
+OVERRIDES = {
+  a: 2,
+  a: 1,
+  b: 1
+  # stays at the bottom
+}
+
+# Values that span lines are left where they are
+# rubyfmt:sort-keys
+MESSAGES = {
+  welcome: <<~TEXT,
+    Hello
+  TEXT
+  bye: "Bye"
+}
+
+# rubyfmt:sort-keys
+SIZES = {
+  small: [
+    1,
+    2
+  ],
+  big: 3
+}
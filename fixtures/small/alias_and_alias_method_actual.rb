@@ -0,0 +1,13 @@
+class Foo
+  alias  bar   baz
+  alias bar? baz?
+  alias :qux :quux
+  alias :"dyna" :"mic"
+  alias_method :new_name, :old_name
+  alias_method(:other, :thing)
+  alias_method :with_space ,  :sloppy
+end
+
+alias top_level original
+alias_method :x,:y
+Foo.send(:alias_method, :a, :b)
@@ -0,0 +1,5 @@
+USERS = [{id: 1, name: "Alice", admin: true}, {id: 2, name: "Bob", admin: false}, {id: 3, name: "Caroline", admin: false}]
+
+rows = [
+  {a: 1, b: 2}, {a: 3, b: 4}
+]
@@ -0,0 +1,27 @@
+if (x = compute)
+  use x
+end
+
+if x = compute
+  use x
+end
+
+if (match = PATTERN.match(line)) && match[1]
+  handle match
+end
+
+while line = gets
+  puts line
+end
+
+while (chunk = io.read(1024))
+  buffer << chunk
+end
+
+until (node = queue.shift).nil?
+  visit node
+end
+
+unless (value = cache[key])
+  value = cache[key] = load(key)
+end
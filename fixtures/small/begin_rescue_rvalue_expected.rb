@@ -0,0 +1,22 @@
+x = begin
+  risky
+rescue
+  fallback
+end
+
+value = begin
+  Integer(input)
+rescue ArgumentError => e
+  log(e)
+  0
+end
+
+def config
+  @config ||= begin
+    parse(File.read(path))
+  rescue Errno::ENOENT
+    {}
+  ensure
+    cleanup
+  end
+end
@@ -0,0 +1,28 @@
+def first
+  1
+end
+def second
+  2
+end
+
+
+# Documented
+def third
+  3
+end
+
+class Widget
+  def a
+    1
+  end
+  def b
+    2
+  end
+
+  # Documented
+  def c
+    3
+  end
+end
+def after_class
+end
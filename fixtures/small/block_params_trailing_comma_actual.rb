@@ -0,0 +1,13 @@
+pairs.each { |key,| puts(key) }
+
+pairs.each { |a, b,| puts(a) }
+
+pairs.each do |key,|
+  puts(key)
+end
+
+pairs.map do |(a, b), c,|
+  a + c
+end
+
+pairs.each { |a, b| puts(a) }
@@ -0,0 +1,28 @@
+exact_fit(fits_01, fits_02, fits_03, fits_04, fits_05, fits_06, fits_07, fits_08, fits_09, fits_10, fits_11, fits_12, zzzzzzzzzzzz)
+
+just_over(
+  over_01,
+  over_02,
+  over_03,
+  over_04,
+  over_05,
+  over_06,
+  over_07,
+  over_08,
+  over_09,
+  over_10,
+  over_11,
+  over_12,
+  zzzzzzzzzzzzz
+)
+
+outer_call(
+  first_outer_argument,
+  inner_call(inner_01, inner_02, inner_03, inner_04, inner_05, zzzzzzzzzz),
+  qqqqqqqqqqqqqqqqqqqqqqqqq
+)
+
+already_broken(
+  a,
+  b
+)
@@ -0,0 +1,24 @@
+outer_method_name(
+  first_argument_value_which_is_long,
+  inner_method_name(second_argument_value, third_argument_value),
+  fourth_argument_value_here
+)
+
+outer_method_name(
+  first_argument_value,
+  inner_method_name(
+    second_argument_value,
+    third_argument_value,
+    another_long_argument_name,
+    yet_another_long_argument_name,
+    the_very_last_one
+  )
+)
+
+[
+  first_list_element_with_a_long_name,
+  second_list_element,
+  {first_key: first_hash_value, second_key: second_hash_value, third_key: third}
+]
+
+short_call(a, nested(b, c))
@@ -0,0 +1,15 @@
+def process
+      # misindented comment
+  step_one
+# another one
+  #   with internal spacing kept
+  step_two
+end
+
+def tree
+# rubyfmt:keep-comment-indent
+# root
+  # child
+    # grandchild
+  build
+end
@@ -0,0 +1,13 @@
+# stdlib
+require "json"
+require "set"
+
+# gems
+require "rails"
+require "sidekiq"
+# internal
+require "my_app/config"
+# require "my_app/legacy"
+require "my_app/jobs"
+# Entry point
+MyApp.boot
@@ -0,0 +1,17 @@
+foo(
+  a,
+  # explain b
+  b
+)
+
+class Worker
+  def call
+    schedule(
+      # the queue is fixed
+      :default,
+      job_id,
+      # retries are handled upstream
+      retry: false
+    )
+  end
+end
@@ -0,0 +1,28 @@
+valid = a == b && c != d
+same = x.eql?(y) || x.equal?(z)
+matched = (kind == :a || kind == :b) && !value.nil?
+order = (left <=> right) == 0
+
+if user.role == :admin && user.active? != false
+  allow
+end
+
+unless left === right || left.eql?(right)
+  reject
+end
+
+if a == b && c != d
+  go
+end
+
+if first_value_to_compare == second_value_to_compare &&
+    third_value_to_compare != fourth_value_to_compare ||
+    fallback_enabled?
+  fallback
+end
+
+while (current_position <=> target_position) != 0 &&
+    attempts_remaining > 0 &&
+    queue_is_still_accepting_work?(current_position)
+  step
+end
@@ -0,0 +1,25 @@
+def process(items)
+  items.each do |item|
+    next if item.nil?
+    if item.done?
+      break item.result
+    end
+
+    unless item.valid?
+      next
+    end
+
+    return build_a_really_long_response_name(
+      item.identifier_for_the_response,
+      item.payload_with_a_rather_long_name,
+      item.metadata_for_the_response,
+      item.extra_response_options
+    )
+  end
+
+  items.map { |item| next item.value }
+  items.find do |item|
+    break item if item.ready?
+    item.next_candidate
+  end
+end
@@ -0,0 +1,17 @@
+Hash[pairs]
+Hash[[[:a, 1], [:b, 2]]]
+Hash[keys.zip(values)]
+Array(x)
+Array(nil)
+Array([1, 2])
+String(n)
+Integer("42", 10)
+Float(value, exception: false)
+Rational(1, 3)
+
+def to_list
+  Array(@items).map { |item| String(item) }
+end
+
+hash = Hash[pairs]
+hash[:key]
@@ -0,0 +1,27 @@
+if defined?(Rails)
+    Rails.logger.info("loaded")
+end
+
+unless Object.const_defined?(:Foo)
+  Foo = Class.new
+end
+
+require "json" unless defined?(JSON)
+
+if defined?(Rails) && Rails.env.production?
+  enable_caching
+end
+
+if defined? Bundler
+  Bundler.setup
+end
+
+module Compat
+  if !defined?(@loaded)
+      @loaded = true
+  end
+
+  def self.ready?
+    defined?(@loaded) && const_defined?(:VERSION, false)
+  end
+end
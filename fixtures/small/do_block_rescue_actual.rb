@@ -0,0 +1,27 @@
+items.each do |item|
+  process(item)
+rescue ArgumentError => e
+  log(e)
+else
+  done
+ensure
+  cleanup
+end
+
+class Worker
+  def perform
+    jobs.each do |job|
+      # Run the job
+      job.run
+    rescue StandardError => e
+      # Jobs that blow up are retried later
+      retry_later(job, e)
+    end
+  end
+end
+
+run_safely do
+  work
+rescue
+  nil
+end
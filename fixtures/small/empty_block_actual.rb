@@ -1 +1,3 @@
 foo {}
+bar { |x| }
+baz { |x, y|   }
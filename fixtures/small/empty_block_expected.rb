@@ -1 +1,3 @@
-foo {  }
+foo {}
+bar { |x| }
+baz { |x, y| }
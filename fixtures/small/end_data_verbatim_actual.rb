@@ -0,0 +1,10 @@
+x=DATA.read.split(",")
+puts x.inspect
+
+
+__END__
+  one,two  ,
+three
+
+
+four   
\ No newline at end of file
@@ -0,0 +1,40 @@
+def save
+  write_file
+  if changed?
+    notify
+  end
+ensure
+  unlock
+  log("saved")
+end
+
+def fetch
+  response = get(url)
+  response.body
+ensure
+  if response
+      response.close
+  end
+
+  cleanup
+end
+
+def process
+  begin
+    step
+  rescue Timeout => e
+    retry_later(e)
+  ensure
+    teardown
+    items.each do |item|
+      item.release
+    end
+  end
+end
+
+begin
+  run
+ensure
+  stop
+  exit(status)
+end
@@ -0,0 +1,7 @@
+class Foo
+  def bar
+  end
+end
+
+# frozen at the bottom
+# and a second line
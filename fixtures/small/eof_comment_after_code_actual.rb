@@ -0,0 +1,5 @@
+a = 1
+puts(a)
+# last line
+
+
@@ -0,0 +1,4 @@
+def foo
+  1
+end
+# last line
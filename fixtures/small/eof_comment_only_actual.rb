@@ -0,0 +1 @@
+# the only line
\ No newline at end of file
@@ -0,0 +1 @@
+# the only line
@@ -0,0 +1,14 @@
+a.+(b)
+a.-(b)
+a.*(b)
+a.**(2)
+a.<=>(b)
+a.==(b)
+a.!=(b)
+a.<<(item)
+a.[](0)
+a.[]=(0, value)
+a.-@
+a.!
+a&.+(b)
+total = prices.reduce(0) { |sum, price| sum.+(price) }
@@ -0,0 +1,25 @@
+def process(user, options)
+  return unless user
+  return if user.banned?
+  return unless options[:enabled]
+  raise ArgumentError, "missing name" unless user.name
+  raise ArgumentError, "missing email" if user.email.nil?
+
+  user.process!
+end
+
+def tight_guards(a, b, c)
+  return unless a
+  return unless b
+  return unless c
+  if a > b
+    a
+  end
+end
+
+items.each do |item|
+  next unless item
+  next if item.skip?
+  break if item.last?
+  item.call
+end
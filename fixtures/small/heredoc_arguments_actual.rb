@@ -0,0 +1,23 @@
+execute(<<~SQL, user_id)
+  SELECT *
+  FROM users
+  WHERE id = ?
+SQL
+
+execute(<<~SQL, user_id, limit: 10)
+  SELECT * FROM posts WHERE user_id = ?
+SQL
+
+compare(<<~EXPECTED, <<~ACTUAL)
+  expected text
+EXPECTED
+  actual text
+ACTUAL
+
+def report
+  write_file(<<~HEADER, <<~BODY, mode: "w")
+    # Report
+  HEADER
+    Everything is fine.
+  BODY
+end
@@ -0,0 +1,37 @@
+execute(
+  <<~SQL,
+    SELECT *
+    FROM users
+    WHERE id = ?
+  SQL
+  user_id
+)
+
+execute(
+  <<~SQL,
+    SELECT * FROM posts WHERE user_id = ?
+  SQL
+  user_id,
+  limit: 10
+)
+
+compare(
+  <<~EXPECTED,
+    expected text
+  EXPECTED
+  <<~ACTUAL
+    actual text
+  ACTUAL
+)
+
+def report
+  write_file(
+    <<~HEADER,
+      # Report
+    HEADER
+    <<~BODY,
+      Everything is fine.
+    BODY
+    mode: "w"
+  )
+end
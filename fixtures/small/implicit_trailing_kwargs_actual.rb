@@ -0,0 +1,14 @@
+foo(a, b: 1, c: 2)
+foo a, b: 1, c: 2
+foo(b: 1, c: 2)
+
+foo({a: 1})
+foo(a, {b: 1, c: 2})
+foo({a: 1}, b: 2)
+
+create_the_record(record_attributes, validate: true, touch_timestamps: false, notify_subscribers: true, retries: 3)
+create_the_record(record_attributes, {validate: true, touch_timestamps: false, notify_subscribers: true, retries: 3})
+
+foo(a,
+  b: 1,
+  c: 2)
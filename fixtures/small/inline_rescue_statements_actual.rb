@@ -0,0 +1,8 @@
+port = Integer(ENV["PORT"]) rescue 3000
+File.delete(path) rescue nil
+
+def parse(input)
+  JSON.parse(input) rescue nil
+end
+
+total = count + (fetch rescue 0)
@@ -5,5 +5,5 @@ lambda { |x| x }
   arg
   arg
 }
--> (arg) {  }
--> {  }
+-> (arg) {}
+-> {}
@@ -0,0 +1,19 @@
+[1, 2, 3].map { |x| x * 2 }.select(&:even?)
+
+[
+  "first_long_list_element",
+  "second_long_list_element",
+].map { |x| x.upcase }.select { |x| x.start_with?("F") }
+
+{
+  alpha: 1,
+  beta: 2,
+}.each do |key, value|
+  puts "#{key}: #{value}"
+end
+
+records.where(status: "active", created_at: start_time..end_time, owner_id: current_user.id, archived: false).order(:created_at).limit(10)
+
+def grouped
+  items.group_by(&:kind).fetch(:book, [some_default_value_that_is_long, another_default_value_that_is_long, third_one]).first
+end
@@ -0,0 +1,17 @@
+a, b = foo
+a, b = b, a
+a, b = foo
+a = [b, c]
+a = b, c
+a = *b
+a = [*b]
+*a, b = arr
+a, *b = arr
+a, *b, c = arr
+*a = arr
+a, = arr
+a, = arr
+a, (b, c) = 1, [2, 3]
+@a, @b = 1, 2
+foo.a, foo.b = 1, 2
+h[:a], h[:b] = 1, 2
@@ -0,0 +1,24 @@
+class User
+  def valid?
+    !name.nil?
+  end
+
+  def save!
+    raise "invalid" unless valid?
+    persist
+  end
+
+  def name=(value)
+    @name = value
+  end
+
+  def self.enabled?
+    true
+  end
+end
+
+user = User.new
+user.name = "bob"
+user.save! if user.valid?
+User.enabled?
+user.public_send(:name=, "alice")
@@ -0,0 +1,8 @@
+def notify
+  deliver(user, message, priority: :high,
+    channel: "email", retry_on_failure: true)
+  deliver(*recipients, template: "welcome", locals: {name: user.name},
+          **options)
+end
+
+client.request(:post, "/api/v1/organizations/widgets", body: serialized_payload, headers: default_request_headers, timeout: request_timeout, retries: 5)
@@ -0,0 +1,22 @@
+module Outer
+  VERSION = "1.0"
+  class Inner::Widget < Base
+  end
+  module Nested
+    module Deeper
+      class Leaf
+        def call
+          1
+        end
+      end
+    end
+    module Sibling
+    end
+  end
+end
+class Outer::Reopened
+  def call
+    2
+  end
+end
+module A; module B; end; end
@@ -0,0 +1,7 @@
+a = "Hello #{name || "stranger"}"
+b = "Items: #{items.join(", ")}"
+c = "Nested #{"inner #{"deepest"}"} done"
+d = "Quote #{"\""} inside"
+e = "Lookup #{hash["key"]} and #{hash[:"other-key"]}"
+f = `echo #{"hi"}`
+g = "Percent #{"it's"} and #{"say \"#{word}\""}"
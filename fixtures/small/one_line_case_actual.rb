@@ -0,0 +1,5 @@
+case x; when 1 then :a; when 2 then :b; end
+label = case status when :ok then "fine" when :error, :fatal then "broken" else "unknown" end
+def kind(value)
+  case value when String then :string else :other end
+end
@@ -0,0 +1,24 @@
+case x
+when 1
+  :a
+when 2
+  :b
+end
+
+label = case status
+when :ok
+  "fine"
+when :error, :fatal
+  "broken"
+else
+  "unknown"
+end
+
+def kind(value)
+  case value
+  when String
+    :string
+  else
+    :other
+  end
+end
@@ -0,0 +1,15 @@
+module Empty; end
+class Blank < StandardError; end
+module Single; VERSION = "1.0"; end
+class Point; attr_reader :x; end
+class Expanded
+end
+module AlsoExpanded
+  VERSION = "2.0"
+end
+
+module Errors
+  class NotFound < StandardError; end
+  class Timeout < StandardError; end
+  def self.all; [NotFound, Timeout]; end
+end
@@ -0,0 +1,6 @@
+def fetch; load_remote; rescue; nil; end
+
+class Client
+  def call; perform; rescue Timeout::Error => e; retry_later(e); end
+  def close; socket.close; rescue IOError; nil; ensure; @socket = nil; end
+end
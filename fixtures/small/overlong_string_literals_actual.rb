@@ -0,0 +1,5 @@
+warn("The configuration file could not be found in any of the expected locations, so the formatter will fall back to its defaults")
+
+def load
+  fetch(path, "The configuration file could not be found in any of the expected locations, so the formatter will fall back to its defaults", strict: true)
+end
@@ -0,0 +1,14 @@
+def check
+  return if valid?
+  return unless user.admin?
+  do_thing if ready?
+  save! if dirty?
+  puts("loaded") if block_given?
+end
+
+valid?
+user.save!
+ok = list.empty? || item.nil?
+x = ready? ? 1 : 2
+loaded? ? reload! : load!
+flag = !ready?
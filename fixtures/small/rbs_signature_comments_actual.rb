@@ -0,0 +1,21 @@
+class Repository
+  #: (String) -> void
+  def initialize(path)
+    @path = path
+  end
+  #: (
+  #:   Integer id,
+  #:   ?include_deleted: bool
+  #: ) -> Record?
+  def find(id, include_deleted: false)
+    lookup(id, include_deleted)
+  end
+  # @rbs return: Array[Record]
+  def all
+    records
+  end
+end
+#: (Integer) -> String
+def label(n)
+  n.to_s
+end
@@ -0,0 +1,29 @@
+class Importer
+  def run
+    rows.each do |row|
+      begin
+        import(row)
+      rescue InvalidRow => e
+        skip(row, e)
+      end
+    end
+  rescue StandardError => e
+    report(e)
+  ensure
+    close
+  end
+
+  def self.call
+    new.run
+  rescue Interrupt
+    nil
+  end
+end
+
+begin
+  Importer.call
+rescue => e
+  warn(e.message)
+ensure
+  exit
+end
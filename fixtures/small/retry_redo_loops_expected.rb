@@ -0,0 +1,36 @@
+def fetch(url)
+  attempts = 0
+  begin
+    attempts += 1
+    get(url)
+  rescue Timeout::Error
+    retry if attempts < 3
+    raise
+  end
+end
+
+def download(url)
+  get(url)
+rescue IOError
+  sleep(1)
+  retry
+end
+
+def read_lines(lines)
+  lines.each do |line|
+    line = line.strip
+    redo if line.empty?
+    process(line)
+  end
+end
+
+def drain(queue)
+  while queue.any?
+    item = queue.pop
+    if item.nil?
+      redo
+    end
+
+    process(item)
+  end
+end
@@ -0,0 +1,28 @@
+if obj&.valid?
+  save(obj)
+end
+
+unless user&.profile&.complete?
+  redirect_to(edit_profile_path)
+end
+
+notify(user) if user&.email&.present?
+return unless account&.active?
+
+value = record&.parent&.name || "none"
+
+while (node = node&.next)
+  visit(node)
+end
+
+account
+  &.subscriptions
+  &.active
+  &.first
+  &.cancel!
+
+result = current_organization
+  &.memberships
+  &.where(role: :admin)
+  &.includes(:user)
+  &.map { |membership| membership.user.email }
@@ -0,0 +1,16 @@
+Foo::Bar::Baz::Qux
+Foo::Bar::Baz
+::TopLevel
+::TopLevel
+::TopLevel::Nested
+
+Foo::BAR = 1
+::TOP_LEVEL = 2
+
+Foo::bar
+Foo::Bar.baz(1)
+Foo::Bar::baz(1)
+
+class Foo::Bar < ::Base::Class
+  include ::Comparable
+end
@@ -0,0 +1,11 @@
+@cache ||= compute
+@cache ||= compute(1, 2)
+@enabled &&= check?
+@flags[:key] ||= {}
+@client ||= Client.new(host: "localhost", port: 80)
+@value &&= @value.strip
+x = y || z
+x = y && z
+x = fetch(key) || default_value
+x = a && b.call(1, 2)
+x = a || b && c
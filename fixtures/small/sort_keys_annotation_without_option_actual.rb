@@ -0,0 +1,5 @@
+# rubyfmt:sort-keys
+LIMITS = {
+  "uploads" => 10,
+  "downloads" => 5
+}
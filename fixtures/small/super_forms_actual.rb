@@ -0,0 +1,35 @@
+class Child < Parent
+  def initialize(a, b)
+    super
+  end
+
+  def reset(a, b)
+    super()
+  end
+
+  def call(a, b)
+    super(a, b)
+  end
+
+  def command(a, b)
+    super a, b
+  end
+
+  def with_block(a)
+    super do |result|
+      result.finish
+    end
+  end
+
+  def forward_block(a, &block)
+    super(a, &block)
+  end
+
+  def value
+    super || default_value
+  end
+
+  def other
+    super().compact
+  end
+end
@@ -0,0 +1,13 @@
+foo(
+  a,
+  b # last
+)
+
+class Worker
+  def call
+    schedule(
+      queue,
+      job_id # the job to run
+    )
+  end
+end
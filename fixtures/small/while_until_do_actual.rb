@@ -0,0 +1,25 @@
+while queue.any? do
+  process(queue.pop)
+end
+until done? do
+  step
+end
+while running
+  tick
+end
+
+i += 1 while i < 10
+i -= 1 until i.zero?
+
+begin
+  attempt
+end while retrying?
+
+items.each do |item|
+  count = 0
+  while item.pending? do
+    item.advance
+    count += 1
+  end
+  count
+end
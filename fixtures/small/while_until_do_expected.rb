@@ -0,0 +1,28 @@
+while queue.any?
+  process(queue.pop)
+end
+
+until done?
+  step
+end
+
+while running
+  tick
+end
+
+i += 1 while i < 10
+i -= 1 until i.zero?
+
+begin
+  attempt
+end while retrying?
+
+items.each do |item|
+  count = 0
+  while item.pending?
+    item.advance
+    count += 1
+  end
+
+  count
+end
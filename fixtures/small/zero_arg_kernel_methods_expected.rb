@@ -0,0 +1,9 @@
+root = File.join(__dir__, "lib")
+binding.pry
+binding.local_variable_get(:x)
+trace = caller
+first = caller(1).first
+locations = caller_locations
+name = __method__
+yield if block_given?
+ctx = binding
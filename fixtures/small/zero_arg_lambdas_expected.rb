@@ -0,0 +1,10 @@
+a = -> { 1 }
+b = -> { 1 }
+c = -> { 1 }
+d = -> { 1 }
+e = -> { 1 }
+f = -> {}
+g = -> do
+  1
+end
+items.map(&-> { 1 })
@@ -24,6 +24,10 @@ enum Rubyfmt_FormatError {
     // a programming error. Please file a bug report and terminate the process
     // and restart.
     RUBYFMT_OTHER_RUBY_ERROR = 4,
+
+    // ruby stopped reading the buffer before its end, i.e. at a NUL byte, so
+    // formatting it would drop code. Non fatal, like a syntax error.
+    RUBYFMT_FORMAT_ERROR_INCOMPLETE_PARSE = 6,
 };
 
 typedef struct _RubyfmtString RubyfmtString;
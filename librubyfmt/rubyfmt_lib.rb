@@ -37,7 +37,13 @@ class Parser < Ripper::SexpBuilderPP
     @heredoc_regex = /(<<[-~]?)(.*$)/
     @regexp_stack = []
     @embexpr_stack = []
+    # where each interpolation starts and ends, as [line, column] pairs
+    @embexpr_position_stack = []
+    # the source of each interpolation, for strings that are kept as written
+    @embexpr_sources = {}.compare_by_identity
     @string_stack = []
+    @last_string_end = nil
+    @symbeg_stack = []
     @kw_stacks = {
       "do" => [],
       "ensure" => [],
@@ -64,9 +70,40 @@ class Parser < Ripper::SexpBuilderPP
     @rbracket_stack = []
     @lbrace_stack = []
     @comments = {}
+    # columns of the comments that are on a line of their own
+    @comment_columns = {}
+    @keep_comment_indents = false
     @last_ln = 0
     # binary contents comming after a `__END__` node
     @data_contents_start_line = nil
+    @preserve_strings = false
+  end
+
+  # Keep string literals exactly as they were written, rather than
+  # converting them to double quoted strings
+  def preserve_strings!
+    @preserve_strings = true
+  end
+
+  KEEP_COMMENT_INDENT_MARKER = "# rubyfmt:keep-comment-indent"
+
+  # Keep the indentation of every line of a comment block relative to its
+  # first line, rather than only for blocks that start with the marker
+  def keep_comment_indents!
+    @keep_comment_indents = true
+  end
+
+  # The first syntax error Ruby reported, as [line, column, message]
+  attr_reader :syntax_error
+
+  def on_parse_error(message)
+    @syntax_error ||= [lineno, column, message]
+    super
+  end
+
+  def compile_error(message)
+    @syntax_error ||= [lineno, column, message]
+    super
   end
 
   def on_nl(*args)
@@ -111,6 +148,8 @@ class Parser < Ripper::SexpBuilderPP
         nil
       end
 
+      indent_kept_comment_blocks
+
       [res, @comments, @lines_with_any_ruby, @last_ln, data_contents]
     end
   end
@@ -246,8 +285,15 @@ class Parser < Ripper::SexpBuilderPP
     super + [start_end_for_keyword('until')]
   end
 
+  SORT_KEYS_MARKER = "# rubyfmt:sort-keys"
+
+  # The last element says whether the hash is annotated for sorting, with
+  # the marker either on the line above the hash or after its `{`
   def on_hash(assocs)
-    [:hash, assocs, [@lbrace_stack.pop, lineno]]
+    start_line = @lbrace_stack.pop
+    sort_keys = @comments[start_line - 1]&.strip == SORT_KEYS_MARKER ||
+      @comments[start_line]&.strip&.end_with?(SORT_KEYS_MARKER) || false
+    [:hash, assocs, [start_line, lineno], sort_keys]
   end
 
   def on_zsuper
@@ -433,7 +479,11 @@ class Parser < Ripper::SexpBuilderPP
 
       args << [start_line, end_line]
 
-      if start_delim && end_delim && start_delim != "\""
+      if @preserve_strings
+        keep_interpolation_verbatim(args[0])
+        # hand the delimiters over, so that they can be written back out
+        args << [start_delim, end_delim] if start_delim && end_delim && start_delim != "\""
+      elsif start_delim && end_delim && start_delim != "\""
         if start_delim == "'" || start_delim.start_with?("%q")
           # re-evaluate the string with its own quotes to handle escaping.
           if args[0][1]
@@ -495,6 +545,39 @@ class Parser < Ripper::SexpBuilderPP
     [:lambda, *args]
   end
 
+  # Replaces the interpolations in the parts of a string with the source
+  # they were written as, so that they're kept exactly as they are
+  def keep_interpolation_verbatim(string_content)
+    string_content.map! do |part|
+      next part unless part.is_a?(Array)
+
+      case part[0]
+      when :string_embexpr
+        source, position = @embexpr_sources.delete(part)
+        source ? [:@tstring_content, source, position] : part
+      when :string_dvar
+        # i.e. `"#@foo"`, which is `[:var_ref, [:@ivar, "@foo", [line, column]]]`
+        variable = part[1][0] == :var_ref ? part[1][1] : part[1]
+        _, name, (line, column) = variable
+        [:@tstring_content, "##{name}", [line, column - 1]]
+      else
+        part
+      end
+    end
+  end
+
+  # The source between two [line, column] positions, where columns are in
+  # bytes like Ripper's
+  def source_between((start_line, start_column), (end_line, end_column))
+    if start_line == end_line
+      @file_lines[start_line - 1].byteslice(start_column...end_column)
+    else
+      @file_lines[start_line - 1].byteslice(start_column..-1) +
+        @file_lines[start_line...(end_line - 1)].join +
+        @file_lines[end_line - 1].byteslice(0...end_column)
+    end
+  end
+
   def on_tstring_beg(*args, &blk)
     @string_stack << [args[0], lineno]
     super
@@ -502,31 +585,74 @@ class Parser < Ripper::SexpBuilderPP
 
   def on_tstring_end(*args, &blk)
     @string_stack << [args[0], lineno]
+    @last_string_end = :tstring_end
+    super
+  end
+
+  def on_label_end(*args)
+    @last_string_end = :label_end
     super
   end
 
   def on_embexpr_beg(*args)
     @embexpr_stack << [lineno]
+    @embexpr_position_stack << [[lineno, column]]
     super
   end
 
   def on_embexpr_end(*args)
     # Append end line to make a StartEnd
     @embexpr_stack.last << lineno
+    @embexpr_position_stack.last << [lineno, column + args[0].bytesize]
     super
   end
 
   def on_string_embexpr(*args)
-    super + [@embexpr_stack.pop]
+    node = super + [@embexpr_stack.pop]
+    start_position, end_position = @embexpr_position_stack.pop
+    if @preserve_strings && end_position
+      source = source_between(start_position, end_position)
+      # A heredoc's body comes after the string, so it has to be formatted
+      # along with the code it's in
+      unless source.match?(/<<[~-]?["'`A-Za-z_]/)
+        @embexpr_sources[node] = [source, start_position]
+      end
+    end
+    node
+  end
+
+  def on_symbeg(*args)
+    @symbeg_stack << args[0]
+    super
+  end
+
+  def on_symbol(*args)
+    @symbeg_stack.pop
+    super
   end
 
   def on_dyna_symbol(*args)
     # dyna_symbol expressions still end up calling
     # on_tstring_end, which will append the closing
     # quote to @string_stack. We want to ignore this,
-    # so remove it from the stack.
-    start_line = @string_stack.pop[1]
-    super + [[start_line, lineno]]
+    # so remove it from the stack. Labels like `"foo bar":`
+    # end with a label_end instead, so for those it's the
+    # opening quote.
+    delim, start_line = @string_stack.pop
+    label = @last_string_end == :label_end
+    symbeg = @symbeg_stack.pop unless label
+    res = super + [[start_line, lineno]]
+
+    if @preserve_strings
+      keep_interpolation_verbatim(res[1])
+      start_delim = label ? delim : symbeg&.delete_prefix(":")
+      # `%s(...)` can't be written after a `:`, so it's normalized like
+      # double quotes are
+      if start_delim && start_delim != "\"" && !start_delim.start_with?("%")
+        res << [start_delim, delim]
+      end
+    end
+    res
   end
 
   def on_regexp_beg(re_part)
@@ -539,8 +665,38 @@ class Parser < Ripper::SexpBuilderPP
     super(*args)
   end
 
+  # Ruby stops reading a file at a NUL, ^D or ^Z byte, and whatever comes
+  # after it is left out of the tree without any error. Returns the first
+  # line after the last token that isn't blank, if there is one.
+  def unlexed_line
+    return nil if @data_contents_start_line
+
+    last_lexed_line = [@lines_with_any_ruby.keys.max, @comments.keys.max].compact.max || 0
+    idx = @file_lines[last_lexed_line..].index { |line| !line.strip.empty? }
+    idx && last_lexed_line + idx + 1
+  end
+
   def on_comment(comment)
     @comments[lineno] = comment
+    if @file_lines[lineno - 1][0...column].strip.empty?
+      @comment_columns[lineno] = column
+    end
+  end
+
+  # Comments get re-indented one line at a time, so to keep the layout of
+  # a block (i.e. ASCII art), bake how much further each line is indented
+  # than the first into the comment itself
+  def indent_kept_comment_blocks
+    blocks = @comment_columns.keys.sort.slice_when { |a, b| b != a + 1 }
+    blocks.each do |lines|
+      next unless @keep_comment_indents || @comments[lines.first].strip == KEEP_COMMENT_INDENT_MARKER
+
+      first_column = @comment_columns[lines.first]
+      lines.drop(1).each do |line|
+        extra_indent = [@comment_columns[line] - first_column, 0].max
+        @comments[line] = " " * extra_indent + @comments[line]
+      end
+    end
   end
 
   def on___end__(val)
@@ -0,0 +1,326 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Options controlling the discretionary parts of rubyfmt's output.
+/// `Config::default()` is what `format_buffer` uses.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// Whether there's a blank line between a `class` or `module` line and
+    /// the `include`/`extend`/`prepend` calls directly after it.
+    pub mixin_spacing: MixinSpacing,
+    /// How many spaces the lines of a broken collection, argument list or
+    /// call chain are indented by. Defaults to 2, like every other indent.
+    pub continuation_indent: u32,
+    /// Whether the output always ends with exactly one newline. When this
+    /// is off, whatever newlines the source ended with are kept as they are.
+    /// In a file with a `__END__`, this is about the code before it, since
+    /// the data after it is always copied over byte for byte.
+    pub normalize_final_newline: bool,
+    /// Whether string literals are rewritten as double quoted strings. When
+    /// this is off they keep their quotes, escapes and `%q`/`%Q` delimiters,
+    /// the code inside `#{}` is left as it was written, and so are quoted
+    /// symbols like `:'foo bar'`.
+    pub normalize_strings: bool,
+    /// What happens to `rescue` modifiers on statements and the right hand
+    /// side of assignments, i.e. `x = foo rescue bar`.
+    pub inline_rescue: InlineRescue,
+    /// How the lines of a comment block are indented.
+    pub comment_indent: CommentIndent,
+    /// How many blank lines go between a top level `def` and the `end` right
+    /// before it, which is usually another method's. The comments above a
+    /// `def` count as part of it. There's always at least one.
+    pub blank_lines_between_defs: u32,
+    /// The same as `blank_lines_between_defs`, for methods inside a class,
+    /// module or block.
+    pub blank_lines_between_nested_defs: u32,
+    /// What happens to the parens around the whole condition of an `if`,
+    /// `unless`, `elsif`, `while` or `until`, i.e. `if (x > 0)`.
+    pub condition_parens: ConditionParens,
+    /// Whether the hashes in a multiline array of single line hashes with
+    /// the same keys are lined up like a table, i.e. `{id: 1,  name: "a"}`
+    /// above `{id: 10, name: "b"}`. Only arrays without comments whose rows
+    /// still fit on a line after padding are aligned.
+    pub align_hash_rows: bool,
+    /// Whether hashes annotated with a `# rubyfmt:sort-keys` comment, on the
+    /// line above the hash or after its `{`, have their entries sorted by
+    /// key. Only hashes with one entry per line and plain symbol, label or
+    /// string keys are sorted, and entries with the same key keep their
+    /// order. Hashes without the annotation are never reordered.
+    pub sort_hash_keys: bool,
+    /// Whether the `when` and `else` lines of a `case` line up with the
+    /// `case`, or are indented one level past it. The branch bodies are
+    /// always indented one level past their `when`.
+    pub case_when_indent: CaseWhenIndent,
+    /// Whether the bodies of classes and modules that are empty or a single
+    /// statement go on the same line as the `class` and `end`, i.e.
+    /// `class Foo; BAR = 1; end`.
+    pub class_body_style: ClassBodyStyle,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            mixin_spacing: MixinSpacing::default(),
+            continuation_indent: 2,
+            normalize_final_newline: true,
+            normalize_strings: true,
+            inline_rescue: InlineRescue::default(),
+            comment_indent: CommentIndent::default(),
+            blank_lines_between_defs: 1,
+            blank_lines_between_nested_defs: 1,
+            condition_parens: ConditionParens::default(),
+            align_hash_rows: false,
+            sort_hash_keys: false,
+            case_when_indent: CaseWhenIndent::default(),
+            class_body_style: ClassBodyStyle::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Starts building a `Config` from the defaults, for setting only the
+    /// options you care about:
+    ///
+    /// ```
+    /// use rubyfmt::{Config, MixinSpacing};
+    ///
+    /// let config = Config::builder()
+    ///     .mixin_spacing(MixinSpacing::Never)
+    ///     .continuation_indent(4)
+    ///     .build();
+    ///
+    /// assert_eq!(config.mixin_spacing, MixinSpacing::Never);
+    /// assert_eq!(config.continuation_indent, 4);
+    /// assert_eq!(Config::builder().build(), Config::default());
+    /// ```
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    pub fn mixin_spacing(mut self, mixin_spacing: MixinSpacing) -> Self {
+        self.config.mixin_spacing = mixin_spacing;
+        self
+    }
+
+    pub fn continuation_indent(mut self, continuation_indent: u32) -> Self {
+        self.config.continuation_indent = continuation_indent;
+        self
+    }
+
+    pub fn normalize_final_newline(mut self, normalize_final_newline: bool) -> Self {
+        self.config.normalize_final_newline = normalize_final_newline;
+        self
+    }
+
+    pub fn normalize_strings(mut self, normalize_strings: bool) -> Self {
+        self.config.normalize_strings = normalize_strings;
+        self
+    }
+
+    pub fn inline_rescue(mut self, inline_rescue: InlineRescue) -> Self {
+        self.config.inline_rescue = inline_rescue;
+        self
+    }
+
+    pub fn comment_indent(mut self, comment_indent: CommentIndent) -> Self {
+        self.config.comment_indent = comment_indent;
+        self
+    }
+
+    pub fn blank_lines_between_defs(mut self, blank_lines_between_defs: u32) -> Self {
+        self.config.blank_lines_between_defs = blank_lines_between_defs;
+        self
+    }
+
+    pub fn blank_lines_between_nested_defs(mut self, blank_lines_between_nested_defs: u32) -> Self {
+        self.config.blank_lines_between_nested_defs = blank_lines_between_nested_defs;
+        self
+    }
+
+    pub fn condition_parens(mut self, condition_parens: ConditionParens) -> Self {
+        self.config.condition_parens = condition_parens;
+        self
+    }
+
+    pub fn align_hash_rows(mut self, align_hash_rows: bool) -> Self {
+        self.config.align_hash_rows = align_hash_rows;
+        self
+    }
+
+    pub fn sort_hash_keys(mut self, sort_hash_keys: bool) -> Self {
+        self.config.sort_hash_keys = sort_hash_keys;
+        self
+    }
+
+    pub fn case_when_indent(mut self, case_when_indent: CaseWhenIndent) -> Self {
+        self.config.case_when_indent = case_when_indent;
+        self
+    }
+
+    pub fn class_body_style(mut self, class_body_style: ClassBodyStyle) -> Self {
+        self.config.class_body_style = class_body_style;
+        self
+    }
+
+    pub fn build(self) -> Config {
+        self.config
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum MixinSpacing {
+    /// Keep a blank line if the source had one.
+    #[default]
+    Preserve,
+    /// Always put a blank line before the leading mixins.
+    Always,
+    /// Never put a blank line before the leading mixins.
+    Never,
+}
+
+impl FromStr for MixinSpacing {
+    type Err = UnknownOptionValue;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "preserve" => Ok(MixinSpacing::Preserve),
+            "always" => Ok(MixinSpacing::Always),
+            "never" => Ok(MixinSpacing::Never),
+            _ => Err(UnknownOptionValue(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum InlineRescue {
+    /// Leave `rescue` modifiers on one line.
+    #[default]
+    Preserve,
+    /// Rewrite them as a `begin` block with a bare `rescue`, which rescues
+    /// `StandardError` just like the modifier does.
+    Expand,
+}
+
+impl FromStr for InlineRescue {
+    type Err = UnknownOptionValue;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "preserve" => Ok(InlineRescue::Preserve),
+            "expand" => Ok(InlineRescue::Expand),
+            _ => Err(UnknownOptionValue(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum CommentIndent {
+    /// Indent every line to the level of the surrounding code, except in
+    /// blocks that start with a `# rubyfmt:keep-comment-indent` line, which
+    /// are treated like `Keep`.
+    #[default]
+    Normalize,
+    /// Indent the first line of each block to the level of the surrounding
+    /// code, and keep how much further in the lines after it were.
+    Keep,
+}
+
+impl FromStr for CommentIndent {
+    type Err = UnknownOptionValue;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "normalize" => Ok(CommentIndent::Normalize),
+            "keep" => Ok(CommentIndent::Keep),
+            _ => Err(UnknownOptionValue(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum ConditionParens {
+    /// Leave the parens as they were written.
+    #[default]
+    Keep,
+    /// Remove them when the condition is a single line expression that
+    /// means the same without them. Conditions that are assignments keep
+    /// their parens, since those are usually there to show that the
+    /// assignment is on purpose.
+    Strip,
+}
+
+impl FromStr for ConditionParens {
+    type Err = UnknownOptionValue;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(ConditionParens::Keep),
+            "strip" => Ok(ConditionParens::Strip),
+            _ => Err(UnknownOptionValue(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum CaseWhenIndent {
+    /// Put `when` at the same indent as `case`.
+    #[default]
+    Aligned,
+    /// Indent `when` one level past `case`.
+    Indented,
+}
+
+impl FromStr for CaseWhenIndent {
+    type Err = UnknownOptionValue;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "aligned" => Ok(CaseWhenIndent::Aligned),
+            "indented" => Ok(CaseWhenIndent::Indented),
+            _ => Err(UnknownOptionValue(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum ClassBodyStyle {
+    /// Always put the body on its own lines between the `class` and `end`.
+    #[default]
+    Expanded,
+    /// Put empty and single statement bodies on one line with the `class`
+    /// and `end`, as long as they have no comments, fit on a single line
+    /// and don't have a `rescue` or `ensure`. Other bodies are expanded.
+    Compact,
+}
+
+impl FromStr for ClassBodyStyle {
+    type Err = UnknownOptionValue;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "expanded" => Ok(ClassBodyStyle::Expanded),
+            "compact" => Ok(ClassBodyStyle::Compact),
+            _ => Err(UnknownOptionValue(s.to_string())),
+        }
+    }
+}
+
+/// Returned when parsing an option from a string that isn't one of its
+/// accepted values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownOptionValue(pub String);
+
+impl fmt::Display for UnknownOptionValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown value `{}`", self.0)
+    }
+}
+
+impl std::error::Error for UnknownOptionValue {}
@@ -0,0 +1,136 @@
+//! Formatting to a list of text edits, for language servers that want to
+//! send the changes instead of replacing the whole document.
+
+use std::ops::Range;
+
+use similar::{capture_diff_slices, Algorithm, DiffTag};
+
+use crate::config::Config;
+use crate::partial_format::{line_offset, line_starts};
+use crate::{format_buffer_with_config, RichFormatError};
+
+/// A place in a document, as in the Language Server Protocol: `line` is
+/// zero indexed, and `character` is the number of UTF-16 code units before
+/// it on that line.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub line: u32,
+    pub character: u32,
+}
+
+/// A change to a document: the text in `range` is replaced with `new_text`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub range: Range<Position>,
+    pub new_text: String,
+}
+
+/// Formats `buf` with `config`, and returns the edits that turn `buf` into
+/// the formatted output. The edits are in order and don't overlap, and their
+/// positions are all in `buf`, so they can be handed to an editor as they
+/// are.
+///
+/// Lines that formatting doesn't change aren't touched, and each edit only
+/// covers the part of the lines between the first and last characters that
+/// changed:
+///
+/// ```
+/// use rubyfmt::{Config, Position, TextEdit};
+///
+/// assert_eq!(rubyfmt::rubyfmt_init(), 0);
+///
+/// let position = |line, character| Position { line, character };
+/// let edits = rubyfmt::format_edits("a = 1\nfoo 1,2\nb = 2\n", &Config::default()).unwrap();
+/// assert_eq!(
+///     edits,
+///     vec![TextEdit {
+///         range: position(1, 3)..position(1, 7),
+///         new_text: "(1, 2)".to_string(),
+///     }]
+/// );
+///
+/// // Characters are counted in UTF-16 code units, so the emoji counts twice
+/// let edits = rubyfmt::format_edits("x = '😀'+y\n", &Config::default()).unwrap();
+/// assert_eq!(
+///     edits,
+///     vec![TextEdit {
+///         range: position(0, 4)..position(0, 9),
+///         new_text: "\"😀\" + ".to_string(),
+///     }]
+/// );
+///
+/// let formatted = rubyfmt::format_buffer("puts 'hi'\n").unwrap();
+/// assert_eq!(rubyfmt::format_edits(&formatted, &Config::default()).unwrap(), vec![]);
+/// ```
+pub fn format_edits(buf: &str, config: &Config) -> Result<Vec<TextEdit>, RichFormatError> {
+    let formatted = format_buffer_with_config(buf, config)?;
+    let old_lines: Vec<&str> = buf.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = formatted.split_inclusive('\n').collect();
+    let old_starts = line_starts(buf);
+    let new_starts = line_starts(&formatted);
+
+    // Runs of changed lines, as byte ranges of `buf` and `formatted`
+    let mut hunks: Vec<(Range<usize>, Range<usize>)> = Vec::new();
+    let mut previous_changed = false;
+    for op in capture_diff_slices(Algorithm::Myers, &old_lines, &new_lines) {
+        let (tag, old_range, new_range) = op.as_tag_tuple();
+        if tag == DiffTag::Equal {
+            previous_changed = false;
+            continue;
+        }
+
+        let old_bytes = line_offset(&old_starts, buf, old_range.start)
+            ..line_offset(&old_starts, buf, old_range.end);
+        let new_bytes = line_offset(&new_starts, &formatted, new_range.start)
+            ..line_offset(&new_starts, &formatted, new_range.end);
+        match hunks.last_mut() {
+            Some((old_hunk, new_hunk)) if previous_changed => {
+                old_hunk.end = old_bytes.end;
+                new_hunk.end = new_bytes.end;
+            }
+            _ => hunks.push((old_bytes, new_bytes)),
+        }
+        previous_changed = true;
+    }
+
+    Ok(hunks
+        .into_iter()
+        .map(|(old_bytes, new_bytes)| {
+            let (prefix, suffix) =
+                unchanged_ends(&buf[old_bytes.clone()], &formatted[new_bytes.clone()]);
+            let start = old_bytes.start + prefix;
+            TextEdit {
+                range: position_of(buf, &old_starts, start)
+                    ..position_of(buf, &old_starts, old_bytes.end - suffix),
+                new_text: formatted[new_bytes.start + prefix..new_bytes.end - suffix].to_string(),
+            }
+        })
+        .collect())
+}
+
+/// The lengths in bytes of the common prefix and suffix of `old` and `new`,
+/// which don't overlap.
+fn unchanged_ends(old: &str, new: &str) -> (usize, usize) {
+    let prefix: usize = old
+        .chars()
+        .zip(new.chars())
+        .take_while(|(o, n)| o == n)
+        .map(|(o, _)| o.len_utf8())
+        .sum();
+    let suffix: usize = old[prefix..]
+        .chars()
+        .rev()
+        .zip(new[prefix..].chars().rev())
+        .take_while(|(o, n)| o == n)
+        .map(|(o, _)| o.len_utf8())
+        .sum();
+    (prefix, suffix)
+}
+
+fn position_of(buf: &str, line_starts: &[usize], offset: usize) -> Position {
+    let line = line_starts.partition_point(|start| *start <= offset) - 1;
+    Position {
+        line: line as u32,
+        character: buf[line_starts[line]..offset].encode_utf16().count() as u32,
+    }
+}
@@ -1,5 +1,6 @@
 use std::collections::{BTreeMap, BTreeSet};
 use std::mem;
+use std::ops::Range;
 
 use log::debug;
 
@@ -36,8 +37,14 @@ impl FileComments {
             if lineno < 0 {
                 raise("line number negative");
             }
+            // Only the end is trimmed, which doesn't change any comment apart
+            // from the ones in blocks that keep their indentation: Ripper's
+            // comment tokens start at the `#`, so the only comments that
+            // start with spaces are the lines of those blocks, which
+            // `indent_kept_comment_blocks` in rubyfmt_lib.rb indents further
+            // than the block's first line. See `Config::comment_indent`.
             let comment = unsafe { ruby_string_to_str(*ruby_comment) }
-                .trim()
+                .trim_end()
                 .to_owned();
             fc.push_comment(lineno as _, comment);
         }
@@ -59,6 +66,30 @@ impl FileComments {
         self.other_comments.contains_key(&line_number)
     }
 
+    pub fn has_ruby_on_line(&self, line_number: LineNumber) -> bool {
+        self.lines_with_ruby.contains(&line_number)
+    }
+
+    /// The last line before `line_number` that has code on it.
+    pub fn last_ruby_line_before(&self, line_number: LineNumber) -> Option<LineNumber> {
+        self.lines_with_ruby
+            .range(..line_number)
+            .next_back()
+            .copied()
+    }
+
+    pub fn take_comment_on_line(&mut self, line_number: LineNumber) -> Option<String> {
+        self.other_comments.remove(&line_number)
+    }
+
+    /// Removes the comments on `lines`, in the order they're in the file.
+    pub fn take_comments_in_lines(&mut self, lines: Range<LineNumber>) -> Vec<String> {
+        let mut rest = self.other_comments.split_off(&lines.start);
+        let mut after = rest.split_off(&lines.end);
+        self.other_comments.append(&mut after);
+        rest.into_values().collect()
+    }
+
     /// Add a new comment. If the beginning of this file is a comment block,
     /// each of those comment lines must be pushed before any other line, or
     /// the end of the block from the start of the file will be incorrectly calculated.
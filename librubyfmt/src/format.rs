@@ -1,9 +1,13 @@
+use crate::config::{CaseWhenIndent, ClassBodyStyle, ConditionParens, InlineRescue};
 use crate::delimiters::BreakableDelims;
 use crate::heredoc_string::HeredocKind;
 use crate::parser_state::{BaseParserState, ConcreteParserState, FormattingContext, RenderFunc};
+use crate::render_queue_writer::MAX_LINE_LENGTH;
 use crate::ripper_tree_types::*;
+use crate::transforms::TransformKind;
 use crate::types::LineNumber;
 use log::debug;
+use std::ops::Range;
 
 pub fn format_def(ps: &mut dyn ConcreteParserState, def: Def) {
     let def_expression = (def.1).to_def_parts();
@@ -106,7 +110,19 @@ pub fn inner_format_params(ps: &mut dyn ConcreteParserState, params: Box<Params>
     }
 }
 
+fn has_block_params(bv: &BlockVar) -> bool {
+    let has_locals = matches!(bv.2, BlockLocalVariables::Present(_));
+    match &bv.1 {
+        Some(params) => params.non_null_positions().iter().any(|&v| v) || has_locals,
+        None => has_locals,
+    }
+}
+
 pub fn format_blockvar(ps: &mut dyn ConcreteParserState, bv: BlockVar) {
+    if !has_block_params(&bv) {
+        return;
+    }
+
     let start_end = bv.3;
     let f_params = match bv.2 {
         BlockLocalVariables::Present(v) => Some(v),
@@ -115,15 +131,6 @@ pub fn format_blockvar(ps: &mut dyn ConcreteParserState, bv: BlockVar) {
 
     let params = bv.1;
 
-    let have_any_params = match &params {
-        Some(params) => params.non_null_positions().iter().any(|&v| v) || f_params.is_some(),
-        None => f_params.is_some(),
-    };
-
-    if !have_any_params {
-        return;
-    }
-
     ps.new_block(Box::new(|ps| {
         ps.breakable_of(
             BreakableDelims::for_block_params(),
@@ -146,6 +153,7 @@ pub fn format_blockvar(ps: &mut dyn ConcreteParserState, bv: BlockVar) {
                                         f_params.into_iter().map(Expression::Ident).collect(),
                                         None,
                                         true,
+                                        false,
                                     );
                                 }),
                             );
@@ -272,8 +280,12 @@ pub fn format_rest_param(
         Box::new(|ps| {
             match rest_param {
                 None => {}
-                Some(RestParamOr0OrExcessedCommaOrArgsForward::ExcessedComma(_)) => {}
-                Some(RestParamOr0OrExcessedCommaOrArgsForward::Zero(_)) => {}
+                // Not a `Comma`, since those get cleaned up when they're
+                // the last thing in a breakable
+                Some(RestParamOr0OrExcessedCommaOrArgsForward::ExcessedComma(_))
+                | Some(RestParamOr0OrExcessedCommaOrArgsForward::Zero(_)) => {
+                    ps.emit_ident(",".to_string());
+                }
                 Some(RestParamOr0OrExcessedCommaOrArgsForward::ArgsForward(_)) => {
                     ps.emit_ellipsis();
                 }
@@ -702,6 +714,8 @@ pub fn use_parens_for_method_call(
         }
     }
 
+    // `super` without parens forwards the current method's arguments, and
+    // `super()` passes none, so adding or removing them changes behaviour
     if name == "super" || name == "require" || name == "require_relative" {
         return original_used_parens;
     }
@@ -832,6 +846,7 @@ pub fn format_method_call(ps: &mut dyn ConcreteParserState, method_call: MethodC
                                         args,
                                         maybe_end_line,
                                         force_single_line,
+                                        true,
                                     );
                                     ps.emit_collapsing_newline();
                                 }),
@@ -866,11 +881,15 @@ pub enum SpecialCase {
     RestParamOutsideOfParamDef,
 }
 
+/// Formats the items of a list. With `keeps_trailing_comment`, a comment
+/// after the last item of a multiline list stays on the item's line, i.e. a
+/// `b # last` before the closing paren of a call.
 pub fn format_list_like_thing_items(
     ps: &mut dyn ConcreteParserState,
     args: Vec<Expression>,
     end_line: Option<LineNumber>,
     single_line: bool,
+    keeps_trailing_comment: bool,
 ) -> bool {
     let mut emitted_args = false;
     let skip_magic_comments = args
@@ -888,6 +907,15 @@ pub fn format_list_like_thing_items(
                     ps.emit_comma_space();
                 }
             } else {
+                let is_last = idx == args_count - 1;
+                // Everything before the last item has been formatted, so any
+                // code left before the end of the list is part of it
+                let trailing_comment = match end_line {
+                    Some(end_line) if is_last && keeps_trailing_comment => {
+                        ps.take_trailing_comment(end_line)
+                    }
+                    _ => None,
+                };
                 ps.with_start_of_line(
                     false,
                     Box::new(|ps| {
@@ -902,10 +930,13 @@ pub fn format_list_like_thing_items(
                                 format_expression(ps, expr);
                             }
                         }
-                        if idx != args_count - 1 {
+                        if !is_last {
                             ps.emit_comma();
                             ps.emit_soft_newline();
                         } else {
+                            if let Some(comment) = trailing_comment {
+                                ps.emit_trailing_comment(comment);
+                            }
                             ps.shift_comments();
                         }
                     }),
@@ -1391,9 +1422,13 @@ pub fn format_array(ps: &mut dyn ConcreteParserState, array: Array) {
     ps.on_line((array.2).0);
 
     match array.1 {
-        SimpleArrayOrPercentArray::SimpleArray(a) => {
-            format_array_fast_path(ps, array.2.end_line(), a)
-        }
+        SimpleArrayOrPercentArray::SimpleArray(a) => match hash_table_rows(ps, &a, &array.2) {
+            Some(rows) => {
+                ps.record_transform(TransformKind::AlignedHashRows, (array.2).0);
+                format_hash_table(ps, rows)
+            }
+            None => format_array_fast_path(ps, array.2.end_line(), a),
+        },
         SimpleArrayOrPercentArray::LowerPercentArray(pa) => {
             ps.on_line((pa.2).0);
             format_percent_array(
@@ -1417,6 +1452,138 @@ pub fn format_array(ps: &mut dyn ConcreteParserState, array: Array) {
     ps.wind_dumping_comments_until_line((array.2).1);
 }
 
+/// The entries of each hash in an array that `Config::align_hash_rows`
+/// lines up, along with how many spaces of padding go after each one.
+type HashTableRows = Vec<Vec<(AssocNewOrAssocSplat, usize)>>;
+
+fn hash_table_rows(
+    ps: &mut dyn ConcreteParserState,
+    a: &Option<ArgsAddStarOrExpressionListOrArgsForward>,
+    start_end: &StartEnd,
+) -> Option<HashTableRows> {
+    let (start_line, end_line) = (start_end.start_line(), start_end.end_line());
+    if !ps.config().align_hash_rows
+        || start_line == end_line
+        || ps.has_comments_in_line(start_line, end_line)
+    {
+        return None;
+    }
+
+    let elements = match a {
+        Some(ArgsAddStarOrExpressionListOrArgsForward::ExpressionList(el)) if el.len() > 1 => el,
+        _ => return None,
+    };
+    let rows = elements
+        .iter()
+        .map(|e| match e {
+            Expression::Hash(Hash(_, Some(assocs), se, _)) if se.start_line() == se.end_line() => {
+                Some(assocs.1.clone())
+            }
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    let labels = |row: &[AssocNewOrAssocSplat]| {
+        row.iter()
+            .map(|assoc| match assoc {
+                AssocNewOrAssocSplat::AssocNew(new) => match &new.1 {
+                    AssocKey::Label(label) => Some(label.1.clone()),
+                    AssocKey::Expression(_) => None,
+                },
+                AssocNewOrAssocSplat::AssocSplat(_) => None,
+            })
+            .collect::<Option<Vec<_>>>()
+    };
+    let first_labels = labels(&rows[0])?;
+    if first_labels.is_empty()
+        || rows
+            .iter()
+            .any(|row| labels(row).as_ref() != Some(&first_labels))
+    {
+        return None;
+    }
+
+    let widths: Vec<Vec<usize>> = rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|assoc| {
+                    let assoc = assoc.clone();
+                    ps.rendered_width(Box::new(move |ps| format_assoc(ps, assoc, true)))
+                })
+                .collect()
+        })
+        .collect();
+    let columns = first_labels.len();
+    let column_widths: Vec<usize> = (0..columns)
+        .map(|col| widths.iter().map(|row| row[col]).max().unwrap_or(0))
+        .collect();
+
+    // The braces, every column but the last at its full width with a `, `
+    // after it, the last entry and the comma after the row
+    let padded_width = widths
+        .iter()
+        .map(|row| {
+            column_widths[..columns - 1]
+                .iter()
+                .map(|w| w + 2)
+                .sum::<usize>()
+                + row[columns - 1]
+        })
+        .max()
+        .unwrap_or(0)
+        + 3;
+    let indent = (ps.current_spaces() + ps.config().continuation_indent) as usize;
+    if indent + padded_width > MAX_LINE_LENGTH {
+        return None;
+    }
+
+    Some(
+        rows.into_iter()
+            .zip(widths)
+            .map(|(row, row_widths)| {
+                row.into_iter()
+                    .zip(row_widths)
+                    .zip(&column_widths)
+                    .map(|((assoc, width), column_width)| (assoc, column_width - width))
+                    .collect()
+            })
+            .collect(),
+    )
+}
+
+fn format_hash_table(ps: &mut dyn ConcreteParserState, rows: HashTableRows) {
+    ps.breakable_of(
+        BreakableDelims::for_array(),
+        Box::new(|ps| {
+            let len = rows.len();
+            for (idx, row) in rows.into_iter().enumerate() {
+                ps.emit_soft_indent();
+                ps.with_start_of_line(
+                    false,
+                    Box::new(|ps| {
+                        let entries = row.len();
+                        ps.emit_ident("{".to_string());
+                        for (entry_idx, (assoc, padding)) in row.into_iter().enumerate() {
+                            format_assoc(ps, assoc, true);
+                            if entry_idx != entries - 1 {
+                                ps.emit_comma();
+                                ps.emit_ident(" ".repeat(padding + 1));
+                            }
+                        }
+                        ps.emit_ident("}".to_string());
+                    }),
+                );
+                if idx != len - 1 {
+                    ps.emit_comma();
+                    ps.emit_soft_newline();
+                }
+            }
+            ps.emit_collapsing_newline();
+        }),
+    );
+}
+
 pub fn format_array_fast_path(
     ps: &mut dyn ConcreteParserState,
     end_line: LineNumber,
@@ -1431,7 +1598,7 @@ pub fn format_array_fast_path(
             ps.breakable_of(
                 BreakableDelims::for_array(),
                 Box::new(|ps| {
-                    format_list_like_thing(ps, a, Some(end_line), false);
+                    format_list_like_thing(ps, a, Some(end_line), false, false);
                     ps.emit_collapsing_newline();
                 }),
             );
@@ -1444,13 +1611,14 @@ pub fn format_list_like_thing(
     a: ArgsAddStarOrExpressionListOrArgsForward,
     end_line: Option<LineNumber>,
     single_line: bool,
+    keeps_trailing_comment: bool,
 ) -> bool {
     match a {
         ArgsAddStarOrExpressionListOrArgsForward::ArgsAddStar(aas) => {
             let left = aas.1;
             let star = aas.2;
             let right = aas.3;
-            let mut emitted_args = format_list_like_thing(ps, *left, None, single_line);
+            let mut emitted_args = format_list_like_thing(ps, *left, None, single_line, false);
 
             if single_line {
                 // if we're single line, our predecessor didn't emit a trailing comma
@@ -1505,7 +1673,7 @@ pub fn format_list_like_thing(
             emitted_args
         }
         ArgsAddStarOrExpressionListOrArgsForward::ExpressionList(el) => {
-            format_list_like_thing_items(ps, el, end_line, single_line)
+            format_list_like_thing_items(ps, el, end_line, single_line, keeps_trailing_comment)
         }
         ArgsAddStarOrExpressionListOrArgsForward::ArgsForward(_) => {
             ps.emit_ellipsis();
@@ -1650,21 +1818,42 @@ pub fn format_string_literal(ps: &mut dyn ConcreteParserState, sl: StringLiteral
             format_heredoc_string_literal(ps, hd, parts)
         }
         StringLiteral::Normal(_, StringContent(_, parts), start_end) => {
-            if ps.at_start_of_line() {
-                ps.emit_indent();
-            }
+            format_quoted_string_literal(ps, parts, start_end, None)
+        }
+        StringLiteral::Preserved(_, StringContent(_, parts), start_end, delims) => {
+            format_quoted_string_literal(ps, parts, start_end, Some(delims))
+        }
+    }
+}
 
-            ps.on_line(start_end.start_line());
+fn format_quoted_string_literal(
+    ps: &mut dyn ConcreteParserState,
+    parts: Vec<StringContentPart>,
+    start_end: StartEnd,
+    delims: Option<StringDelimiters>,
+) {
+    if ps.at_start_of_line() {
+        ps.emit_indent();
+    }
 
+    ps.on_line(start_end.start_line());
+
+    match delims {
+        Some(StringDelimiters(open, close)) => {
+            ps.emit_string_content(open);
+            format_inner_string(ps, parts, StringType::Quoted);
+            ps.emit_string_content(close);
+        }
+        None => {
             ps.emit_double_quote();
             format_inner_string(ps, parts, StringType::Quoted);
             ps.emit_double_quote();
-
-            if ps.at_start_of_line() {
-                ps.emit_newline();
-            }
         }
     }
+
+    if ps.at_start_of_line() {
+        ps.emit_newline();
+    }
 }
 
 pub fn format_xstring_literal(ps: &mut dyn ConcreteParserState, xsl: XStringLiteral) {
@@ -1860,12 +2049,16 @@ pub fn format_massign(ps: &mut dyn ConcreteParserState, massign: MAssign) {
                 AssignableListOrMLhs::AssignableList(al) => {
                     let length = al.len();
                     for (idx, v) in al.into_iter().enumerate() {
+                        // `a, = foo` needs its trailing comma to stay a massign,
+                        // but `*a, = foo` is a syntax error, a lone splat is enough
+                        let needs_trailing_comma =
+                            length == 1 && !matches!(v, Assignable::RestParam(_));
                         format_assignable(ps, v);
                         let last = idx == length - 1;
                         if !last {
                             ps.emit_comma_space();
                         }
-                        if length == 1 {
+                        if needs_trailing_comma {
                             ps.emit_comma();
                         }
                     }
@@ -1977,6 +2170,14 @@ pub fn format_defined(ps: &mut dyn ConcreteParserState, defined: Defined) {
 }
 
 pub fn format_rescue_mod(ps: &mut dyn ConcreteParserState, rescue_mod: RescueMod) {
+    // Only statements and assigned values are expanded, a `begin` block in
+    // the middle of an expression is harder to read than the modifier
+    let expand = ps.config().inline_rescue == InlineRescue::Expand
+        && (ps.at_start_of_line() || ps.current_formatting_context() == FormattingContext::Assign);
+    if expand {
+        return format_expanded_rescue_mod(ps, rescue_mod);
+    }
+
     if ps.at_start_of_line() {
         ps.emit_indent();
     }
@@ -1997,8 +2198,45 @@ pub fn format_rescue_mod(ps: &mut dyn ConcreteParserState, rescue_mod: RescueMod
     }
 }
 
+fn format_expanded_rescue_mod(ps: &mut dyn ConcreteParserState, rescue_mod: RescueMod) {
+    if ps.at_start_of_line() {
+        ps.emit_indent();
+    }
+
+    ps.emit_begin();
+
+    ps.new_block(Box::new(|ps| {
+        ps.emit_newline();
+        ps.with_start_of_line(
+            true,
+            Box::new(|ps| {
+                format_expression(ps, *rescue_mod.1);
+                // The `rescue` is on the line the expression ends on
+                let rescue_line = ps.current_line_number();
+                ps.record_transform(TransformKind::ExpandedInlineRescue, rescue_line);
+                ps.dedent(Box::new(|ps| {
+                    ps.emit_indent();
+                    ps.emit_rescue();
+                }));
+                ps.emit_newline();
+                format_expression(ps, *rescue_mod.2);
+            }),
+        );
+    }));
+
+    ps.with_start_of_line(
+        true,
+        Box::new(|ps| {
+            ps.emit_end();
+        }),
+    );
+    if ps.at_start_of_line() {
+        ps.emit_newline();
+    }
+}
+
 pub fn format_mrhs_new_from_args(ps: &mut dyn ConcreteParserState, mnfa: MRHSNewFromArgs) {
-    format_list_like_thing(ps, mnfa.1, None, true);
+    format_list_like_thing(ps, mnfa.1, None, true, false);
 
     if let Some(expr) = mnfa.2 {
         ps.emit_comma_space();
@@ -2048,7 +2286,7 @@ pub fn format_next(ps: &mut dyn ConcreteParserState, next: Next) {
                 ArgsAddBlockOrExpressionList::ExpressionList(e) => {
                     if !e.is_empty() {
                         ps.emit_space();
-                        format_list_like_thing_items(ps, e, Some((next.2).1), true);
+                        format_list_like_thing_items(ps, e, Some((next.2).1), true, false);
                     }
                 }
                 ArgsAddBlockOrExpressionList::ArgsAddBlock(aab) => match aab.2 {
@@ -2062,6 +2300,7 @@ pub fn format_next(ps: &mut dyn ConcreteParserState, next: Next) {
                             (aab.1).into_args_add_star_or_expression_list(),
                             Some((next.2).1),
                             true,
+                            false,
                         );
                     }
                 },
@@ -2263,7 +2502,24 @@ pub fn format_paren_or_params(ps: &mut dyn ConcreteParserState, pp: ParenOrParam
 
 // Modules and classes bodies should be treated the same,
 // the only real difference is in the module/class name and inheritance
-fn format_constant_body(ps: &mut dyn ConcreteParserState, bodystmt: Box<BodyStmt>, end_line: u64) {
+fn format_constant_body(
+    ps: &mut dyn ConcreteParserState,
+    header: &dyn Fn(&mut dyn ConcreteParserState),
+    bodystmt: Box<BodyStmt>,
+    start_end: StartEnd,
+) {
+    let StartEnd(start_line, end_line) = start_end;
+    if renders_compact(ps, header, &bodystmt, start_line, end_line) {
+        header(ps);
+        format_compact_constant_body(ps, compact_statements(&bodystmt));
+        ps.on_line(end_line);
+        if ps.at_start_of_line() {
+            ps.emit_newline();
+        }
+        return;
+    }
+
+    header(ps);
     ps.new_block(Box::new(|ps| {
         ps.with_start_of_line(
             true,
@@ -2286,33 +2542,80 @@ fn format_constant_body(ps: &mut dyn ConcreteParserState, bodystmt: Box<BodyStmt
     }
 }
 
-pub fn format_class(ps: &mut dyn ConcreteParserState, class: Class) {
-    if ps.at_start_of_line() {
-        ps.emit_indent();
+/// Whether a class or module body goes on one line with
+/// `ClassBodyStyle::Compact`, see its docs for which ones do.
+fn renders_compact(
+    ps: &mut dyn ConcreteParserState,
+    header: &dyn Fn(&mut dyn ConcreteParserState),
+    bodystmt: &BodyStmt,
+    start_line: LineNumber,
+    end_line: LineNumber,
+) -> bool {
+    if ps.config().class_body_style != ClassBodyStyle::Compact
+        || bodystmt.2.is_some()
+        || bodystmt.3.is_some()
+        || bodystmt.4.is_some()
+        || compact_statements(bodystmt).len() > 1
+        || ps.has_comments_in_line(start_line, end_line)
+    {
+        return false;
     }
 
-    let class_name = class.1;
-    let inherit = class.2;
-    let bodystmt = class.3;
-    let end_line = (class.4).1;
+    let render = |ps: &mut dyn ConcreteParserState| {
+        header(ps);
+        format_compact_constant_body(ps, compact_statements(bodystmt));
+    };
+    // The newline renders any heredocs in the body, which can't be compact
+    let is_multiline = ps.will_render_as_multiline(Box::new(|ps| {
+        render(ps);
+        ps.emit_newline();
+    }));
+    !is_multiline
+        && ps.current_spaces() as usize + ps.rendered_width(Box::new(render)) <= MAX_LINE_LENGTH
+}
 
-    ps.emit_class_keyword();
+fn compact_statements(bodystmt: &BodyStmt) -> Vec<Expression> {
+    bodystmt
+        .1
+        .iter()
+        .filter(|expr| !matches!(expr, Expression::VoidStmt(..)))
+        .cloned()
+        .collect()
+}
+
+/// Renders the `; BAR = 1; end` after the name of a compact class or module.
+fn format_compact_constant_body(ps: &mut dyn ConcreteParserState, statements: Vec<Expression>) {
     ps.with_start_of_line(
         false,
         Box::new(|ps| {
+            ps.with_formatting_context(
+                FormattingContext::ClassOrModule,
+                Box::new(|ps| {
+                    for expr in statements {
+                        ps.emit_ident(";".to_string());
+                        ps.emit_space();
+                        format_expression(ps, expr);
+                    }
+                }),
+            );
+            ps.emit_ident(";".to_string());
             ps.emit_space();
+            ps.emit_end();
+        }),
+    );
+}
 
-            match class_name {
-                ConstPathRefOrConstRefOrTopConstRef::ConstPathRef(cpr) => {
-                    format_const_path_ref(ps, cpr);
-                }
-                ConstPathRefOrConstRefOrTopConstRef::ConstRef(cr) => {
-                    handle_string_and_linecol(ps, (cr.1).1, (cr.1).2);
-                }
-                ConstPathRefOrConstRefOrTopConstRef::TopConstRef(tcr) => {
-                    format_top_const_ref(ps, tcr)
-                }
-            }
+fn format_class_header(
+    ps: &mut dyn ConcreteParserState,
+    class_name: ConstPathRefOrConstRefOrTopConstRef,
+    inherit: Option<Box<Expression>>,
+) {
+    ps.emit_class_keyword();
+    ps.with_start_of_line(
+        false,
+        Box::new(|ps| {
+            ps.emit_space();
+            format_constant_name(ps, class_name);
 
             if let Some(inherit_expression) = inherit {
                 ps.emit_ident(" < ".to_string());
@@ -2320,40 +2623,59 @@ pub fn format_class(ps: &mut dyn ConcreteParserState, class: Class) {
             }
         }),
     );
+}
 
-    format_constant_body(ps, bodystmt, end_line);
+fn format_constant_name(
+    ps: &mut dyn ConcreteParserState,
+    name: ConstPathRefOrConstRefOrTopConstRef,
+) {
+    match name {
+        ConstPathRefOrConstRefOrTopConstRef::ConstPathRef(cpr) => {
+            format_const_path_ref(ps, cpr);
+        }
+        ConstPathRefOrConstRefOrTopConstRef::ConstRef(cr) => {
+            handle_string_and_linecol(ps, (cr.1).1, (cr.1).2);
+        }
+        ConstPathRefOrConstRefOrTopConstRef::TopConstRef(tcr) => format_top_const_ref(ps, tcr),
+    }
 }
 
-pub fn format_module(ps: &mut dyn ConcreteParserState, module: Module) {
+pub fn format_class(ps: &mut dyn ConcreteParserState, class: Class) {
     if ps.at_start_of_line() {
         ps.emit_indent();
     }
 
-    let module_name = module.1;
-    let bodystmt = module.2;
-    let end_line = (module.3).1;
+    let class_name = class.1;
+    let inherit = class.2;
+    format_constant_body(
+        ps,
+        &|ps| format_class_header(ps, class_name.clone(), inherit.clone()),
+        class.3,
+        class.4,
+    );
+}
 
-    ps.emit_module_keyword();
-    ps.with_start_of_line(
-        false,
-        Box::new(|ps| {
-            ps.emit_space();
+pub fn format_module(ps: &mut dyn ConcreteParserState, module: Module) {
+    if ps.at_start_of_line() {
+        ps.emit_indent();
+    }
 
-            match module_name {
-                ConstPathRefOrConstRefOrTopConstRef::ConstPathRef(cpr) => {
-                    format_const_path_ref(ps, cpr);
-                }
-                ConstPathRefOrConstRefOrTopConstRef::ConstRef(cr) => {
-                    handle_string_and_linecol(ps, (cr.1).1, (cr.1).2);
-                }
-                ConstPathRefOrConstRefOrTopConstRef::TopConstRef(tcr) => {
-                    format_top_const_ref(ps, tcr)
-                }
-            }
-        }),
+    let module_name = module.1;
+    format_constant_body(
+        ps,
+        &|ps| {
+            ps.emit_module_keyword();
+            ps.with_start_of_line(
+                false,
+                Box::new(|ps| {
+                    ps.emit_space();
+                    format_constant_name(ps, module_name.clone());
+                }),
+            );
+        },
+        module.2,
+        module.3,
     );
-
-    format_constant_body(ps, bodystmt, end_line);
 }
 
 pub fn format_conditional(
@@ -2372,6 +2694,18 @@ pub fn format_conditional(
     }
     ps.emit_conditional_keyword(kw);
     ps.emit_space();
+    let stripped = if ps.config().condition_parens == ConditionParens::Strip {
+        strip_condition_parens(&cond_expr)
+    } else {
+        None
+    };
+    let cond_expr = match stripped {
+        Some((inner, line)) => {
+            ps.record_transform(TransformKind::StrippedConditionParens, line);
+            inner
+        }
+        None => cond_expr,
+    };
     ps.with_start_of_line(
         false,
         Box::new(|ps| {
@@ -2433,6 +2767,133 @@ pub fn format_conditional(
     }
 }
 
+/// Unwraps `(x > 0)` to `x > 0`, for `ConditionParens::Strip`. Only
+/// expressions which can't swallow anything after the condition are
+/// unwrapped, so that e.g. a `do` block stays part of the condition rather
+/// than becoming the body of a `while`. Returns the unwrapped expression and
+/// the line the parens were on, or `None` if they're kept.
+fn strip_condition_parens(cond_expr: &Expression) -> Option<(Expression, LineNumber)> {
+    let (inner, line) = match cond_expr {
+        Expression::Paren(ParenExpr(_, contents, start_end))
+            if start_end.start_line() == start_end.end_line() =>
+        {
+            let inner = match contents {
+                ParenExpressionOrExpressions::Expression(e) => Some(&**e),
+                ParenExpressionOrExpressions::Expressions(exprs) if exprs.len() == 1 => {
+                    exprs.first()
+                }
+                ParenExpressionOrExpressions::Expressions(_) => None,
+            };
+            (inner, start_end.start_line())
+        }
+        _ => return None,
+    };
+
+    match inner {
+        Some(
+            e @ (Expression::Binary(..)
+            | Expression::Unary(..)
+            | Expression::VarRef(..)
+            | Expression::VCall(..)
+            | Expression::Ident(..)
+            | Expression::Const(..)
+            | Expression::ConstPathRef(..)
+            | Expression::TopConstRef(..)
+            | Expression::Call(..)
+            | Expression::MethodCall(..)
+            | Expression::MethodAddArg(..)
+            | Expression::Aref(..)
+            | Expression::Defined(..)),
+        ) if !may_assign(e) => Some((e.clone(), line)),
+        _ => None,
+    }
+}
+
+/// Whether there might be an assignment anywhere in `expr`, i.e. the one in
+/// `(a = foo) && b`. The parens around a condition with an assignment in it
+/// show that it's meant to be one, so they're kept. Anything this doesn't
+/// look inside of counts as possibly assigning.
+fn may_assign(expr: &Expression) -> bool {
+    match expr {
+        Expression::Assign(..) | Expression::OpAssign(..) | Expression::MAssign(..) => true,
+        Expression::VarRef(..)
+        | Expression::VCall(..)
+        | Expression::Ident(..)
+        | Expression::Const(..)
+        | Expression::TopConstRef(..)
+        | Expression::Kw(..)
+        | Expression::Int(..)
+        | Expression::Float(..)
+        | Expression::Rational(..)
+        | Expression::Imaginary(..)
+        | Expression::Char(..)
+        | Expression::Symbol(..)
+        | Expression::SymbolLiteral(..)
+        | Expression::Backref(..) => false,
+        Expression::Binary(Binary(_, left, _, right)) => may_assign(left) || may_assign(right),
+        Expression::Unary(Unary(_, _, operand)) => may_assign(operand),
+        Expression::Defined(Defined(_, e)) => may_assign(e),
+        Expression::ConstPathRef(ConstPathRef(_, e, _)) => may_assign(e),
+        Expression::Paren(ParenExpr(_, contents, _)) => match contents {
+            ParenExpressionOrExpressions::Expression(e) => may_assign(e),
+            ParenExpressionOrExpressions::Expressions(exprs) => exprs.iter().any(may_assign),
+        },
+        Expression::StringLiteral(
+            StringLiteral::Normal(_, StringContent(_, parts), _)
+            | StringLiteral::Preserved(_, StringContent(_, parts), ..),
+        ) => parts.iter().any(|part| match part {
+            StringContentPart::TStringContent(..) => false,
+            StringContentPart::StringEmbexpr(StringEmbexpr(_, exprs)) => {
+                exprs.iter().any(may_assign)
+            }
+            _ => true,
+        }),
+        Expression::Call(Call(_, left, ..)) => {
+            call_chain_may_assign(&left.clone().into_call_chain())
+        }
+        Expression::MethodCall(MethodCall(_, chain, _, _, args, _)) => {
+            call_chain_may_assign(chain) || args_may_assign(args)
+        }
+        Expression::MethodAddArg(MethodAddArg(_, left, args, _)) => {
+            call_chain_may_assign(&left.clone().into_call_chain())
+                || args_may_assign(&normalize_args(args.clone()))
+        }
+        Expression::Aref(aref) => {
+            may_assign(&aref.1)
+                || aref
+                    .2
+                    .as_ref()
+                    .map(|args| args_may_assign(&normalize_args(args.clone())))
+                    .unwrap_or(false)
+        }
+        _ => true,
+    }
+}
+
+fn call_chain_may_assign(chain: &[CallChainElement]) -> bool {
+    chain.iter().any(|element| match element {
+        CallChainElement::IdentOrOpOrKeywordOrConst(..)
+        | CallChainElement::DotTypeOrOp(..)
+        | CallChainElement::VarRef(..) => false,
+        CallChainElement::ArgsAddStarOrExpressionListOrArgsForward(args, _) => {
+            args_may_assign(args)
+        }
+        CallChainElement::Paren(paren) => may_assign(&Expression::Paren(paren.clone())),
+        CallChainElement::Expression(e) => may_assign(e),
+        CallChainElement::Block(..) => true,
+    })
+}
+
+fn args_may_assign(args: &ArgsAddStarOrExpressionListOrArgsForward) -> bool {
+    match args {
+        ArgsAddStarOrExpressionListOrArgsForward::ExpressionList(exprs) => {
+            exprs.iter().any(may_assign)
+        }
+        ArgsAddStarOrExpressionListOrArgsForward::ArgsForward(..) => false,
+        ArgsAddStarOrExpressionListOrArgsForward::ArgsAddStar(..) => true,
+    }
+}
+
 pub fn format_if(ps: &mut dyn ConcreteParserState, ifs: If) {
     let vifs = ifs.clone();
     format_conditional(ps, *ifs.1, ifs.2, "if".to_string(), ifs.3, Some(ifs.4));
@@ -2589,6 +3050,122 @@ pub fn format_char(ps: &mut dyn ConcreteParserState, c: Char) {
     }
 }
 
+/// The key a hash entry is sorted by for `Config::sort_hash_keys`, without
+/// the colons and quotes that would put `"b"` before `a:`, and the line it's
+/// on. Only plain labels, symbols and strings have one.
+fn sort_key(assoc: &AssocNewOrAssocSplat) -> Option<(String, LineNumber)> {
+    let new = match assoc {
+        AssocNewOrAssocSplat::AssocNew(new) => new,
+        // Moving a `**splat` changes which of the duplicate keys wins
+        AssocNewOrAssocSplat::AssocSplat(_) => return None,
+    };
+    match &new.1 {
+        AssocKey::Label(Label(_, label, LineCol(line, _))) => {
+            Some((label.trim_end_matches(':').to_string(), *line))
+        }
+        AssocKey::Expression(Expression::SymbolLiteral(SymbolLiteral(
+            _,
+            SymbolOrBare::Symbol(symbol),
+            start_end,
+        ))) => match &symbol.1 {
+            IdentOrConstOrKwOrOpOrIvarOrGvarOrCvarOrBacktick::Ident(Ident(_, name, _))
+            | IdentOrConstOrKwOrOpOrIvarOrGvarOrCvarOrBacktick::Const(Const(_, name, _)) => {
+                Some((name.clone(), start_end.start_line()))
+            }
+            _ => None,
+        },
+        AssocKey::Expression(Expression::StringLiteral(
+            StringLiteral::Normal(_, StringContent(_, parts), start_end)
+            | StringLiteral::Preserved(_, StringContent(_, parts), start_end, _),
+        )) => match parts.as_slice() {
+            [StringContentPart::TStringContent(TStringContent(_, contents, _))] => {
+                Some((contents.clone(), start_end.start_line()))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The order the entries of a hash annotated for `Config::sort_hash_keys`
+/// go in, as each entry's index in `assocs` along with the lines of it and
+/// the comments above it. Returns `None` if the hash can't be sorted or
+/// already is.
+///
+/// Every entry has to be on a line of its own between the braces, since a
+/// value that spans lines could hold a heredoc or comments that would end
+/// up in the wrong place.
+fn sorted_assoc_order(
+    ps: &dyn ConcreteParserState,
+    assocs: &[AssocNewOrAssocSplat],
+    start_line: LineNumber,
+    end_line: LineNumber,
+) -> Option<Vec<(usize, Range<LineNumber>)>> {
+    let keys = assocs.iter().map(sort_key).collect::<Option<Vec<_>>>()?;
+    let lines: Vec<LineNumber> = std::iter::once(start_line)
+        .chain(keys.iter().map(|(_, line)| *line))
+        .chain(std::iter::once(end_line))
+        .collect();
+    let one_entry_per_line = lines
+        .windows(2)
+        .all(|w| w[0] < w[1] && !ps.has_ruby_in_lines(w[0] + 1, w[1]));
+    if !one_entry_per_line {
+        return None;
+    }
+
+    let mut order: Vec<usize> = (0..keys.len()).collect();
+    // A stable sort, so entries with the same key stay in the order they
+    // were written in
+    order.sort_by(|a, b| keys[*a].0.cmp(&keys[*b].0));
+    if order.iter().enumerate().all(|(idx, entry)| idx == *entry) {
+        return None;
+    }
+
+    Some(
+        order
+            .into_iter()
+            .map(|idx| (idx, lines[idx] + 1..lines[idx + 1] + 1))
+            .collect(),
+    )
+}
+
+/// Like `format_assocs`, in the order from `sorted_assoc_order`. Each entry
+/// takes its comments along with it, the ones after the last entry stay at
+/// the bottom of the hash.
+fn format_sorted_assocs(
+    ps: &mut dyn ConcreteParserState,
+    assocs: Vec<AssocNewOrAssocSplat>,
+    order: Vec<(usize, Range<LineNumber>)>,
+) {
+    let all_labelish = all_labelish(&assocs);
+    let comments: Vec<_> = order
+        .iter()
+        .map(|(_, lines)| ps.take_comments_in_lines(lines.clone()))
+        .collect();
+    // Skipping straight past the entries keeps their `on_line`s, which
+    // now come out of order, from inserting blank lines between them
+    let entry_lines = order.iter().map(|(_, lines)| lines.end - 1);
+    if let (Some(first), Some(last)) = (entry_lines.clone().min(), entry_lines.max()) {
+        ps.jump_to_line(first);
+        ps.jump_to_line(last);
+    }
+
+    let mut assocs: Vec<Option<_>> = assocs.into_iter().map(Some).collect();
+    let len = order.len();
+    for (idx, ((entry, _), comments)) in order.into_iter().zip(comments).enumerate() {
+        ps.emit_soft_indent();
+        if let Some(comments) = comments {
+            ps.insert_comment_collection(comments);
+        }
+        let assoc = assocs[entry].take().expect("each entry is sorted once");
+        format_assoc(ps, assoc, all_labelish);
+        if idx != len - 1 {
+            ps.emit_comma();
+        }
+        ps.emit_soft_newline();
+    }
+}
+
 pub fn format_hash(ps: &mut dyn ConcreteParserState, hash: Hash) {
     if ps.at_start_of_line() {
         ps.emit_indent();
@@ -2618,10 +3195,23 @@ pub fn format_hash(ps: &mut dyn ConcreteParserState, hash: Hash) {
             }
         }
         Some(assoc_list_from_args) => {
+            let sorted_order = if hash.3 && ps.config().sort_hash_keys {
+                sorted_assoc_order(ps, &assoc_list_from_args.1, start_line, end_line)
+            } else {
+                None
+            };
+            if sorted_order.is_some() {
+                ps.record_transform(TransformKind::SortedHashKeys, start_line);
+            }
             ps.breakable_of(
                 BreakableDelims::for_hash(),
                 Box::new(|ps| {
-                    format_assocs(ps, assoc_list_from_args.1, SpecialCase::NoSpecialCase);
+                    match sorted_order {
+                        Some(order) => format_sorted_assocs(ps, assoc_list_from_args.1, order),
+                        None => {
+                            format_assocs(ps, assoc_list_from_args.1, SpecialCase::NoSpecialCase)
+                        }
+                    }
                     ps.wind_dumping_comments_until_line(end_line);
                 }),
             );
@@ -2743,7 +3333,7 @@ fn format_call_chain_elements(
                     ps.breakable_of(
                         delims,
                         Box::new(|ps| {
-                            format_list_like_thing(ps, aas, end_line, false);
+                            format_list_like_thing(ps, aas, end_line, false, true);
                         }),
                     );
                     if let Some(end_line) = end_line {
@@ -2979,11 +3569,13 @@ pub fn format_brace_block(ps: &mut dyn ConcreteParserState, brace_block: BraceBl
 
     ps.emit_ident("{".to_string());
 
+    let mut has_params = false;
     if let Some(bv) = bv {
+        has_params = has_block_params(&bv);
         format_blockvar(ps, bv);
     }
 
-    render_block_contents(ps, will_render_multiline, body, end_line);
+    render_block_contents(ps, will_render_multiline, body, end_line, has_params);
     ps.emit_ident("}".to_string());
 }
 
@@ -2992,7 +3584,17 @@ fn render_block_contents(
     will_render_multiline: bool,
     body: Vec<Expression>,
     end_line: u64,
+    has_params: bool,
 ) {
+    // Empty blocks are `{}`, or `{ |x| }` with params
+    if !will_render_multiline && body.iter().all(|e| matches!(e, Expression::VoidStmt(..))) {
+        if has_params {
+            ps.emit_space();
+        }
+        ps.wind_dumping_comments_until_line(end_line);
+        return;
+    }
+
     ps.new_block(Box::new(|ps| {
         ps.with_start_of_line(
             will_render_multiline,
@@ -3122,6 +3724,7 @@ pub fn format_keyword(
                 (yield_args.1).into_args_add_star_or_expression_list(),
                 Some(start_end.1),
                 true,
+                false,
             );
         }),
     );
@@ -3260,7 +3863,7 @@ pub fn format_when_or_else(ps: &mut dyn ConcreteParserState, tail: WhenOrElse) {
                         ps.inline_breakable_of(
                             BreakableDelims::for_when(),
                             Box::new(|ps| {
-                                format_list_like_thing(ps, conditionals, None, false);
+                                format_list_like_thing(ps, conditionals, None, false, false);
                             }),
                         );
                     }));
@@ -3331,7 +3934,13 @@ pub fn format_case(ps: &mut dyn ConcreteParserState, case: Case) {
     ps.with_start_of_line(
         true,
         Box::new(|ps| {
-            format_when_or_else(ps, WhenOrElse::When(tail));
+            if ps.config().case_when_indent == CaseWhenIndent::Indented {
+                ps.new_block(Box::new(|ps| {
+                    format_when_or_else(ps, WhenOrElse::When(tail));
+                }));
+            } else {
+                format_when_or_else(ps, WhenOrElse::When(tail));
+            }
             ps.emit_end();
         }),
     );
@@ -3432,7 +4041,7 @@ pub fn format_stabby_lambda(ps: &mut dyn ConcreteParserState, sl: StabbyLambda)
                         brace_contents_will_render_multiline(ps, start_line, end_line, b.clone());
                     ps.emit_space();
                     ps.emit_ident("{".to_string());
-                    render_block_contents(ps, will_render_multiline, b, end_line);
+                    render_block_contents(ps, will_render_multiline, b, end_line, false);
                     ps.emit_ident("}".to_string());
                 }
                 ExpressionListOrBodyStmt::BodyStmt(bs) => {
@@ -3703,7 +4312,7 @@ pub fn format_bare_return_args(
             ps.with_formatting_context(
                 FormattingContext::ArgsList,
                 Box::new(|ps| {
-                    format_list_like_thing(ps, args, None, false);
+                    format_list_like_thing(ps, args, None, false, false);
                     ps.emit_collapsing_newline();
                 }),
             );
@@ -0,0 +1,122 @@
+//! Formatting snippets of Ruby that aren't a whole file, i.e. an editor
+//! selection.
+
+use std::str::FromStr;
+
+use crate::config::{Config, UnknownOptionValue};
+use crate::highlight::{format_classified, TokenClass};
+use crate::partial_format::top_level_statement_lines;
+use crate::{format_buffer_with_config, replace_final_newlines, run_parser_on, RichFormatError};
+
+/// The method name used to wrap method bodies so that they can be parsed.
+const METHOD_BODY_WRAPPER: &str = "__rubyfmt_fragment__";
+
+/// What a fragment passed to `format_fragment` is expected to parse as.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum FragmentKind {
+    /// A whole file, the same as `format_buffer`.
+    #[default]
+    File,
+    /// Exactly one expression.
+    Expression,
+    /// The statements inside a `def`, which can include `rescue`, `ensure`
+    /// and `return`.
+    MethodBody,
+}
+
+impl FromStr for FragmentKind {
+    type Err = UnknownOptionValue;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "file" => Ok(FragmentKind::File),
+            "expression" => Ok(FragmentKind::Expression),
+            "method-body" => Ok(FragmentKind::MethodBody),
+            _ => Err(UnknownOptionValue(s.to_string())),
+        }
+    }
+}
+
+/// Formats `buf` as `kind`. Returns `RichFormatError::FragmentKindMismatch`
+/// if `buf` is valid Ruby, but not a valid `kind`.
+pub fn format_fragment(
+    buf: &str,
+    kind: FragmentKind,
+    config: &Config,
+) -> Result<String, RichFormatError> {
+    match kind {
+        FragmentKind::File => format_buffer_with_config(buf, config),
+        FragmentKind::Expression => {
+            let (tree, _, _) = run_parser_on(buf)?;
+            let statements = top_level_statement_lines(tree).len();
+            if statements != 1 {
+                return Err(RichFormatError::FragmentKindMismatch(format!(
+                    "expected a single expression, found {} statements",
+                    statements
+                )));
+            }
+            format_buffer_with_config(buf, config)
+        }
+        FragmentKind::MethodBody => format_method_body(buf, config),
+    }
+}
+
+fn format_method_body(buf: &str, config: &Config) -> Result<String, RichFormatError> {
+    let wrapped = format!("def {}\n{}\nend\n", METHOD_BODY_WRAPPER, buf);
+    match run_parser_on(&wrapped) {
+        Ok((tree, _, _)) if top_level_statement_lines(tree).len() == 1 => {}
+        // `buf` closed the method itself, i.e. `end; foo`
+        Ok(_) => {
+            return Err(RichFormatError::FragmentKindMismatch(
+                "expected the statements of a single method body".to_string(),
+            ))
+        }
+        // Things like `class` and constant assignment are fine in a file,
+        // but aren't allowed inside a method
+        Err(RichFormatError::SyntaxError(_)) if run_parser_on(buf).is_ok() => {
+            return Err(RichFormatError::FragmentKindMismatch(
+                "this is valid Ruby, but it isn't allowed inside a method body".to_string(),
+            ))
+        }
+        Err(e) => return Err(e),
+    }
+
+    // The lines of the formatted wrapper, and whether each one starts in the
+    // middle of a string, i.e. in a heredoc body. Those are part of the
+    // string, so they keep their indentation.
+    let mut lines = vec![(String::new(), false)];
+    for token in format_classified(&wrapped, config)? {
+        let is_string = token.class == TokenClass::String;
+        for (idx, part) in token.text.split('\n').enumerate() {
+            if idx > 0 {
+                lines.push((String::new(), is_string && !part.is_empty()));
+            }
+            lines
+                .last_mut()
+                .expect("there's always a line")
+                .0
+                .push_str(part);
+        }
+    }
+    if lines
+        .last()
+        .map(|(line, _)| line.is_empty())
+        .unwrap_or(false)
+    {
+        lines.pop();
+    }
+
+    // Drop the wrapper's `def` and `end` lines, and the indentation it added
+    let mut output = String::with_capacity(wrapped.len());
+    for (line, in_string) in &lines[1..lines.len() - 1] {
+        match line.strip_prefix("  ") {
+            Some(unindented) if !in_string => output.push_str(unindented),
+            _ => output.push_str(line),
+        }
+        output.push('\n');
+    }
+    if !config.normalize_final_newline {
+        replace_final_newlines(&mut output, buf, None);
+    }
+    Ok(output)
+}
@@ -0,0 +1,265 @@
+//! Formatting to a stream of classified tokens, for editors and docs tooling
+//! that want to syntax highlight the formatted code without lexing it again.
+
+use std::io;
+use std::vec;
+
+use crate::config::Config;
+use crate::line_tokens::ConcreteLineToken;
+use crate::profile::Profile;
+use crate::render_queue_writer::TokenSink;
+use crate::{code_end, format_into, FinalNewlines, RichFormatError};
+
+/// What kind of Ruby a token is, as far as highlighting goes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TokenClass {
+    /// Keywords, including `end`, `nil`, `true`, `self` and `__END__`.
+    Keyword,
+    /// The quotes and contents of string literals. Interpolated code is
+    /// classified like any other code, and heredoc bodies are strings.
+    String,
+    Comment,
+    /// Operators, including `.`, `&.` and `::`.
+    Operator,
+    /// Brackets, parens, commas and the pipes around block parameters.
+    Punctuation,
+    /// Spaces, indentation and newlines.
+    Whitespace,
+    /// Everything else, i.e. identifiers, constants and numbers.
+    Other,
+}
+
+const KEYWORDS: &[&str] = &[
+    "BEGIN",
+    "END",
+    "__ENCODING__",
+    "__FILE__",
+    "__LINE__",
+    "alias",
+    "and",
+    "begin",
+    "break",
+    "case",
+    "class",
+    "def",
+    "defined?",
+    "do",
+    "else",
+    "elsif",
+    "end",
+    "ensure",
+    "false",
+    "for",
+    "if",
+    "in",
+    "module",
+    "next",
+    "nil",
+    "not",
+    "or",
+    "redo",
+    "rescue",
+    "retry",
+    "return",
+    "self",
+    "super",
+    "then",
+    "true",
+    "undef",
+    "unless",
+    "until",
+    "when",
+    "while",
+    "yield",
+];
+
+impl TokenClass {
+    pub(crate) fn of(token: &ConcreteLineToken) -> TokenClass {
+        use ConcreteLineToken::*;
+
+        match token {
+            HardNewLine | Indent { .. } | Space => TokenClass::Whitespace,
+            Keyword { keyword } => Self::of_text(keyword),
+            DefKeyword
+            | ClassKeyword
+            | ModuleKeyword
+            | DoKeyword
+            | ModKeyword { .. }
+            | ConditionalKeyword { .. }
+            | End
+            | DataEnd => TokenClass::Keyword,
+            DirectPart { part } => Self::of_text(part),
+            Op { .. } | Dot | Ellipsis | ColonColon | LonelyOperator => TokenClass::Operator,
+            CommaSpace | Comma | OpenSquareBracket | CloseSquareBracket | OpenCurlyBracket
+            | CloseCurlyBracket | OpenParen | CloseParen => TokenClass::Punctuation,
+            DoubleQuote | LTStringContent { .. } | SingleSlash | HeredocClose { .. } => {
+                TokenClass::String
+            }
+            Comment { .. } => TokenClass::Comment,
+            Delim { contents } => {
+                if contents.chars().all(|c| "()[]{}|".contains(c)) {
+                    TokenClass::Punctuation
+                } else {
+                    TokenClass::String
+                }
+            }
+            AfterCallChain => TokenClass::Whitespace,
+        }
+    }
+
+    /// Classifies the tokens that are written out as they were given, which
+    /// are most of the identifiers and operators.
+    fn of_text(text: &str) -> TokenClass {
+        let trimmed = text.trim();
+        if trimmed.is_empty() {
+            TokenClass::Whitespace
+        } else if text.contains('\n') || trimmed.starts_with("<<~") || trimmed.starts_with("<<-") {
+            // These are heredoc bodies and the data after `__END__`, since
+            // the contents of every other string literal, kept as written
+            // or not, are `LTStringContent`s. A heredoc opened with `<<~`
+            // or `<<-` starts with its sigil, but a bare `<<FOO` is left
+            // alone, because it can't be told apart from a shift here.
+            TokenClass::String
+        } else if KEYWORDS.contains(&trimmed) {
+            TokenClass::Keyword
+        } else if trimmed.chars().all(|c| "(){}[],;".contains(c)) {
+            TokenClass::Punctuation
+        } else if trimmed.chars().all(|c| "=<>!~+-*/%&|^?:.".contains(c)) {
+            TokenClass::Operator
+        } else {
+            TokenClass::Other
+        }
+    }
+}
+
+/// A piece of the formatted output. Concatenating the text of every token
+/// gives exactly what `format_buffer_with_config` would have returned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassifiedToken {
+    pub class: TokenClass,
+    pub text: String,
+}
+
+/// The tokens of a formatted file, in order, see `format_classified`.
+#[derive(Debug)]
+pub struct ClassifiedTokens(vec::IntoIter<ClassifiedToken>);
+
+impl Iterator for ClassifiedTokens {
+    type Item = ClassifiedToken;
+
+    fn next(&mut self) -> Option<ClassifiedToken> {
+        self.0.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+#[derive(Default)]
+struct ClassifyingSink(Vec<ClassifiedToken>);
+
+impl TokenSink for ClassifyingSink {
+    fn write_token(&mut self, class: TokenClass, ruby: String) -> io::Result<()> {
+        if !ruby.is_empty() {
+            self.0.push(ClassifiedToken { class, text: ruby });
+        }
+        Ok(())
+    }
+}
+
+/// Formats `buf` like `format_buffer_with_config`, but returns the output
+/// as classified tokens instead of a string:
+///
+/// ```
+/// use rubyfmt::{ClassifiedToken, Config, TokenClass};
+///
+/// assert_eq!(rubyfmt::rubyfmt_init(), 0);
+///
+/// let tokens: Vec<ClassifiedToken> =
+///     rubyfmt::format_classified("a = 1 if b # c\n", &Config::default())
+///         .unwrap()
+///         .filter(|t| t.class != TokenClass::Whitespace)
+///         .collect();
+/// let token = |class, text: &str| ClassifiedToken {
+///     class,
+///     text: text.to_string(),
+/// };
+///
+/// assert_eq!(
+///     tokens,
+///     vec![
+///         token(TokenClass::Comment, "# c"),
+///         token(TokenClass::Other, "a"),
+///         token(TokenClass::Operator, "="),
+///         token(TokenClass::Other, "1"),
+///         token(TokenClass::Keyword, " if "),
+///         token(TokenClass::Other, "b"),
+///     ]
+/// );
+///
+/// let source = "puts('hi', nil)\n";
+/// let text: String = rubyfmt::format_classified(source, &Config::default())
+///     .unwrap()
+///     .map(|t| t.text)
+///     .collect();
+/// assert_eq!(text, rubyfmt::format_buffer(source).unwrap());
+///
+/// // Strings that span lines are strings too, including ones kept as written
+/// let config = Config::builder().normalize_strings(false).build();
+/// let classes: Vec<TokenClass> = rubyfmt::format_classified("a = 'x\ny'\n", &config)
+///     .unwrap()
+///     .filter(|t| t.class != TokenClass::Whitespace)
+///     .map(|t| t.class)
+///     .skip(2)
+///     .collect();
+/// assert!(!classes.is_empty());
+/// assert!(classes.iter().all(|class| *class == TokenClass::String));
+/// ```
+pub fn format_classified(buf: &str, config: &Config) -> Result<ClassifiedTokens, RichFormatError> {
+    let mut sink = ClassifyingSink::default();
+    let (_, final_newlines) = format_into(&mut sink, buf, config, &mut Profile::default())?;
+    let mut tokens = sink.0;
+    if let FinalNewlines::Kept { data_len } = final_newlines {
+        replace_final_newlines(&mut tokens, buf, data_len);
+    }
+    Ok(ClassifiedTokens(tokens.into_iter()))
+}
+
+/// The same as `replace_final_newlines` in lib.rs, but for tokens.
+fn replace_final_newlines(
+    tokens: &mut Vec<ClassifiedToken>,
+    source: &str,
+    data_len: Option<usize>,
+) {
+    let is_newline = |c| c == '\n' || c == '\r';
+    let data = match data_len {
+        Some(_) => {
+            let data_end = tokens
+                .iter()
+                .position(|t| t.class == TokenClass::Keyword && t.text == "__END__")
+                .unwrap_or(tokens.len());
+            tokens.split_off(data_end)
+        }
+        None => Vec::new(),
+    };
+    let source = &source[..code_end(source, data_len)];
+
+    while let Some(last) = tokens.last_mut() {
+        last.text
+            .truncate(last.text.trim_end_matches(is_newline).len());
+        if !last.text.is_empty() {
+            break;
+        }
+        tokens.pop();
+    }
+
+    let newlines = &source[source.trim_end_matches(is_newline).len()..];
+    if !newlines.is_empty() {
+        tokens.push(ClassifiedToken {
+            class: TokenClass::Whitespace,
+            text: newlines.to_string(),
+        });
+    }
+    tokens.extend(data);
+}
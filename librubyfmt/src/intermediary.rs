@@ -1,7 +1,8 @@
+use crate::config::{Config, MixinSpacing};
 use crate::line_metadata::LineMetadata;
 use crate::line_tokens::*;
-#[cfg(debug_assertions)]
-use log::debug;
+use crate::transforms::TransformKind;
+use log::info;
 use std::convert::TryInto;
 use std::mem;
 
@@ -10,6 +11,18 @@ pub enum BlanklineReason {
     ComesAfterEnd,
     ClassOrModule,
     EndOfRequireBlock,
+    BeforeMixin,
+}
+
+impl BlanklineReason {
+    fn transform_kind(&self) -> TransformKind {
+        match self {
+            BlanklineReason::ComesAfterEnd => TransformKind::BlankLineAfterEnd,
+            BlanklineReason::ClassOrModule => TransformKind::BlankLineBeforeClassOrModule,
+            BlanklineReason::EndOfRequireBlock => TransformKind::BlankLineAfterRequires,
+            BlanklineReason::BeforeMixin => TransformKind::BlankLineBeforeMixin,
+        }
+    }
 }
 
 pub struct Intermediary {
@@ -17,15 +30,27 @@ pub struct Intermediary {
     index_of_last_hard_newline: usize,
     current_line_metadata: LineMetadata,
     previous_line_metadata: Option<LineMetadata>,
+    // The index of the newline at the end of the last `require`, while the
+    // lines after it have only been comments. Those comments might be the
+    // header of another group of requires, so the end of the require block
+    // isn't known yet.
+    end_of_require_block: Option<usize>,
+    // The transforms applied so far, along with the index of the newline
+    // token each one happened at
+    transforms: Vec<(usize, TransformKind)>,
+    config: Config,
 }
 
 impl Intermediary {
-    pub fn new() -> Self {
+    pub fn new(config: Config) -> Self {
         Intermediary {
             tokens: vec![],
             current_line_metadata: LineMetadata::new(),
             previous_line_metadata: None,
             index_of_last_hard_newline: 0,
+            end_of_require_block: None,
+            transforms: vec![],
+            config,
         }
     }
 
@@ -42,30 +67,30 @@ impl Intermediary {
     // - HardNewline
     // so this method actually pops off the extra empty comment whitespace
     pub fn pop_require_comment_whitespace(&mut self) {
-        self.tokens.pop();
-        self.tokens.pop();
+        self.remove_token(self.tokens.len() - 1);
+        self.remove_token(self.tokens.len() - 1);
         self.index_of_last_hard_newline = self.tokens.len() - 1;
     }
 
     pub fn pop_heredoc_mistake(&mut self) {
-        self.tokens.remove(self.tokens.len() - 1);
-        self.tokens.remove(self.tokens.len() - 1);
+        self.remove_token(self.tokens.len() - 1);
+        self.remove_token(self.tokens.len() - 1);
         self.index_of_last_hard_newline = self.tokens.len() - 1;
     }
 
     pub fn fix_heredoc_delim_indent_mistake(&mut self) {
         // Remove duplicate indent
-        self.tokens.remove(self.tokens.len() - 2);
+        self.remove_token(self.tokens.len() - 2);
     }
 
     pub fn fix_heredoc_direct_part_indent_mistake(&mut self) {
         // Remove duplicate indent
-        self.tokens.remove(self.tokens.len() - 3);
+        self.remove_token(self.tokens.len() - 3);
     }
 
     pub fn fix_heredoc_arg_newline_mistake(&mut self) {
         // Remove duplicate newline
-        self.tokens.remove(self.tokens.len() - 1);
+        self.remove_token(self.tokens.len() - 1);
         self.index_of_last_hard_newline = self.tokens.len() - 1;
     }
 
@@ -86,8 +111,29 @@ impl Intermediary {
         )
     }
 
-    pub fn into_tokens(self) -> Vec<ConcreteLineToken> {
-        self.tokens
+    pub fn into_tokens(self) -> (Vec<ConcreteLineToken>, Vec<(usize, TransformKind)>) {
+        (self.tokens, self.transforms)
+    }
+
+    // Keeps the indices in `self.transforms` pointing at the same tokens
+    fn insert_token(&mut self, idx: usize, token: ConcreteLineToken) {
+        for (transform_idx, _) in self.transforms.iter_mut() {
+            if *transform_idx >= idx {
+                *transform_idx += 1;
+            }
+        }
+        self.tokens.insert(idx, token);
+    }
+
+    fn remove_token(&mut self, idx: usize) {
+        self.transforms
+            .retain(|(transform_idx, _)| *transform_idx != idx);
+        for (transform_idx, _) in self.transforms.iter_mut() {
+            if *transform_idx > idx {
+                *transform_idx -= 1;
+            }
+        }
+        self.tokens.remove(idx);
     }
 
     pub fn push(&mut self, lt: ConcreteLineToken) {
@@ -96,11 +142,7 @@ impl Intermediary {
 
         match &lt {
             ConcreteLineToken::HardNewLine => {
-                if let Some(prev) = &self.previous_line_metadata {
-                    if !self.current_line_metadata.has_require() && prev.has_require() {
-                        self.insert_trailing_blankline(BlanklineReason::EndOfRequireBlock);
-                    }
-                }
+                self.handle_end_of_require_block();
 
                 let mut md = LineMetadata::new();
                 mem::swap(&mut md, &mut self.current_line_metadata);
@@ -142,8 +184,12 @@ impl Intermediary {
                 }
             }
             ConcreteLineToken::DirectPart { part } => {
-                if part == "require" && self.tokens.last().map(|t| t.is_indent()).unwrap_or(false) {
-                    self.current_line_metadata.set_has_require();
+                if self.tokens.last().map(|t| t.is_indent()).unwrap_or(false) {
+                    match part.as_str() {
+                        "require" => self.current_line_metadata.set_has_require(),
+                        "include" | "extend" | "prepend" => self.handle_mixin(),
+                        _ => {}
+                    }
                 }
             }
             ConcreteLineToken::Comment { .. } => {
@@ -174,7 +220,7 @@ impl Intermediary {
                     }
 
                     if module_or_class_before_newline {
-                        self.tokens.pop();
+                        self.remove_token(self.tokens.len() - 1);
                         self.index_of_last_hard_newline = self.tokens.len() - 1;
                     }
                 }
@@ -188,12 +234,122 @@ impl Intermediary {
         self.debug_assert_newlines();
     }
 
+    fn handle_end_of_require_block(&mut self) {
+        let prev_has_require = match &self.previous_line_metadata {
+            Some(prev) => prev.has_require(),
+            None => return,
+        };
+
+        if self.current_line_metadata.has_require() {
+            self.end_of_require_block = None;
+        } else if self.current_line_is_comment() {
+            if prev_has_require {
+                self.end_of_require_block = Some(self.index_of_last_hard_newline);
+            }
+        } else if let Some(idx) = self.end_of_require_block.take() {
+            // The comments after the requires belong to this line, so
+            // the blank line goes before them
+            info!(
+                "inserting blank line: {:?}",
+                BlanklineReason::EndOfRequireBlock
+            );
+            self.insert_token(idx, ConcreteLineToken::HardNewLine);
+            self.transforms
+                .push((idx, BlanklineReason::EndOfRequireBlock.transform_kind()));
+            self.index_of_last_hard_newline += 1;
+        } else if prev_has_require {
+            self.insert_trailing_blankline(BlanklineReason::EndOfRequireBlock);
+        }
+    }
+
+    // Empty comments are blank lines, so they don't count
+    fn current_line_is_comment(&self) -> bool {
+        let line_start = if self.index_of_last_hard_newline == 0 {
+            0
+        } else {
+            self.index_of_last_hard_newline + 1
+        };
+        matches!(
+            self.tokens.get(line_start),
+            Some(ConcreteLineToken::Comment { contents }) if !contents.is_empty()
+        )
+    }
+
     fn handle_end(&mut self) {
         self.current_line_metadata.set_has_end();
     }
 
     fn handle_def(&mut self) {
         self.current_line_metadata.set_has_def();
+        self.insert_blank_lines_between_defs();
+    }
+
+    // The one blank line between a `def` and the `end` before it comes from
+    // `BlanklineReason::ComesAfterEnd`, this adds any more the config asks
+    // for. Only called before the `def` is pushed, so at the start of a line
+    // the tokens end with [.., HardNewLine, Indent].
+    fn insert_blank_lines_between_defs(&mut self) {
+        let depth = match self.tokens.last() {
+            Some(ConcreteLineToken::Indent { depth }) => *depth,
+            _ => return,
+        };
+        let wanted = if depth == 0 {
+            self.config.blank_lines_between_defs
+        } else {
+            self.config.blank_lines_between_nested_defs
+        };
+        if wanted <= 1 || self.tokens.len() < 2 {
+            return;
+        }
+
+        // Walk back over the comments documenting the `def`, counting the
+        // blank lines between them and the `end`
+        let mut line_end = self.tokens.len() - 2;
+        let mut blank_lines = 0;
+        loop {
+            if self.tokens.get(line_end) != Some(&ConcreteLineToken::HardNewLine) {
+                return;
+            }
+            let line_start = self.tokens[..line_end]
+                .iter()
+                .rposition(|t| t == &ConcreteLineToken::HardNewLine)
+                .map(|idx| idx + 1)
+                .unwrap_or(0);
+            let line = &self.tokens[line_start..line_end];
+
+            let is_blank = match line {
+                [] => true,
+                [ConcreteLineToken::Comment { contents }] => contents.is_empty(),
+                _ => false,
+            };
+            if is_blank {
+                blank_lines += 1;
+            } else if line.iter().all(|t| {
+                matches!(
+                    t,
+                    ConcreteLineToken::Comment { .. } | ConcreteLineToken::Indent { .. }
+                )
+            }) {
+                blank_lines = 0;
+            } else if line.last() == Some(&ConcreteLineToken::End) {
+                break;
+            } else {
+                return;
+            }
+
+            if line_start == 0 {
+                return;
+            }
+            line_end = line_start - 1;
+        }
+
+        for _ in blank_lines..wanted {
+            self.insert_token(line_end + 1, ConcreteLineToken::HardNewLine);
+            self.transforms
+                .push((line_end + 1, TransformKind::BlankLineBetweenDefs));
+            self.index_of_last_hard_newline += 1;
+        }
+        self.debug_assert_newlines();
     }
 
     fn handle_do_keyword(&mut self) {
@@ -208,6 +364,45 @@ impl Intermediary {
         }
     }
 
+    // Only called at the start of a line, so the tokens end with
+    // [.., HardNewLine, Indent], or [.., HardNewLine, HardNewLine, Indent]
+    // if there's a blank line before the mixin.
+    fn handle_mixin(&mut self) {
+        if self.config.mixin_spacing == MixinSpacing::Preserve || self.tokens.len() < 2 {
+            return;
+        }
+
+        let indent_idx = self.tokens.len() - 1;
+        let newlines = self.tokens[..indent_idx]
+            .iter()
+            .rev()
+            .take_while(|t| t == &&ConcreteLineToken::HardNewLine)
+            .count();
+        let opens_class_or_module = self.tokens[..indent_idx - newlines]
+            .iter()
+            .rev()
+            .take_while(|t| t != &&ConcreteLineToken::HardNewLine)
+            .any(|t| {
+                t == &ConcreteLineToken::ClassKeyword || t == &ConcreteLineToken::ModuleKeyword
+            });
+        if !opens_class_or_module {
+            return;
+        }
+
+        match (self.config.mixin_spacing, newlines) {
+            (MixinSpacing::Always, 1) => {
+                self.insert_trailing_blankline(BlanklineReason::BeforeMixin);
+            }
+            (MixinSpacing::Never, 2) => {
+                self.remove_token(indent_idx - 1);
+                self.transforms
+                    .push((indent_idx - 2, TransformKind::RemovedBlankLineBeforeMixin));
+                self.index_of_last_hard_newline = self.tokens.len() - 2;
+            }
+            _ => {}
+        }
+    }
+
     fn handle_conditional(&mut self) {
         self.current_line_metadata.set_has_conditional();
     }
@@ -217,17 +412,20 @@ impl Intermediary {
         // [.., Comma, Space, DirectPart {part: ""}, <close_delimiter>]
         // so we remove items at positions length-2 until there is nothing
         // in that position that is garbage.
-        while self.tokens[self.len() - 2].is_single_line_breakable_garbage() {
-            self.tokens.remove(self.len() - 2);
+        while self.len() >= 2 && self.tokens[self.len() - 2].is_single_line_breakable_garbage() {
+            self.remove_token(self.len() - 2);
         }
     }
 
-    pub fn insert_trailing_blankline(&mut self, _bl: BlanklineReason) {
+    pub fn insert_trailing_blankline(&mut self, bl: BlanklineReason) {
         if self.index_of_last_hard_newline <= 2 {
-            self.tokens.insert(
+            info!("inserting blank line: {:?}", bl);
+            self.insert_token(
                 self.index_of_last_hard_newline,
                 ConcreteLineToken::HardNewLine,
             );
+            self.transforms
+                .push((self.index_of_last_hard_newline, bl.transform_kind()));
             self.index_of_last_hard_newline += 1;
             self.debug_assert_newlines();
             return;
@@ -244,14 +442,13 @@ impl Intermediary {
             ) => {}
             (_, Some(&ConcreteLineToken::HardNewLine), Some(&ConcreteLineToken::HardNewLine)) => {}
             (_, _, _) => {
-                #[cfg(debug_assertions)]
-                {
-                    debug!("{:?}", _bl);
-                }
-                self.tokens.insert(
+                info!("inserting blank line: {:?}", bl);
+                self.insert_token(
                     self.index_of_last_hard_newline,
                     ConcreteLineToken::HardNewLine,
                 );
+                self.transforms
+                    .push((self.index_of_last_hard_newline, bl.transform_kind()));
                 self.index_of_last_hard_newline += 1;
                 self.debug_assert_newlines();
             }
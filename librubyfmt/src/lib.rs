@@ -15,29 +15,54 @@ pub type RawStatus = i64;
 #[macro_use]
 pub mod ruby;
 mod comment_block;
+mod config;
 mod de;
 mod delimiters;
+mod edits;
 mod file_comments;
 mod format;
+mod fragment;
 mod heredoc_string;
+mod highlight;
 mod intermediary;
 mod line_metadata;
 mod line_tokens;
+mod markdown;
 mod parser_state;
+mod partial_format;
+mod post_process;
+mod profile;
 mod render_queue_writer;
 mod render_targets;
 mod ripper_tree_types;
 mod ruby_ops;
+mod transforms;
 mod types;
 
+pub use config::{
+    CaseWhenIndent, ClassBodyStyle, CommentIndent, ConditionParens, Config, ConfigBuilder,
+    InlineRescue, MixinSpacing, UnknownOptionValue,
+};
+pub use edits::{format_edits, Position, TextEdit};
 use file_comments::FileComments;
+pub use fragment::{format_fragment, FragmentKind};
+pub use highlight::{format_classified, ClassifiedToken, ClassifiedTokens, TokenClass};
+pub use markdown::{format_markdown, MarkdownBlockError};
 use parser_state::BaseParserState;
+pub use partial_format::{format_incremental, format_range, SourceEdit};
+pub use post_process::{format_with_post_processor, PostProcessor};
+use profile::timed;
+pub use profile::Profile;
+use render_queue_writer::TokenSink;
 use ruby_ops::{load_rubyfmt, ParseError, Parser, RipperTree};
+use transforms::FoundTransforms;
+pub use transforms::{AppliedTransform, TransformKind};
+use types::{ColNumber, LineNumber};
 
 #[cfg(debug_assertions)]
 use log::debug;
-#[cfg(debug_assertions)]
-use simplelog::{ConfigBuilder, LevelFilter, TermLogger, TerminalMode};
+use log::LevelFilter;
+use simplelog::{ConfigBuilder as LogConfigBuilder, TermLogger, TerminalMode};
 
 extern "C" {
     pub fn Init_ripper();
@@ -45,6 +70,50 @@ extern "C" {
 
 pub struct RubyfmtString(Box<str>);
 
+/// Where Ruby reported a syntax error, and what it said about it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxErrorLocation {
+    /// Starting from 1.
+    pub line: LineNumber,
+    /// The byte offset into the line, starting from 0.
+    pub column: ColNumber,
+    pub message: String,
+}
+
+/// How many lines before the error are shown by `source_context`
+const CONTEXT_LINES: usize = 2;
+
+impl SyntaxErrorLocation {
+    fn snippet(&self, source: &str) -> String {
+        let lines: Vec<&str> = source.lines().collect();
+        let error_idx = (self.line as usize).saturating_sub(1);
+        let first_idx = error_idx.saturating_sub(CONTEXT_LINES);
+        let width = (error_idx + 1).to_string().len();
+
+        let mut snippet = String::new();
+        for idx in first_idx..=error_idx {
+            let line = lines.get(idx).copied().unwrap_or("");
+            snippet.push_str(&format!("{:>width$} | {}\n", idx + 1, line, width = width));
+        }
+
+        // Columns are in bytes, but the caret goes under the character
+        let line = lines.get(error_idx).copied().unwrap_or("");
+        let column = (self.column as usize).min(line.len());
+        let offset = line
+            .char_indices()
+            .take_while(|(byte, _)| *byte < column)
+            .count();
+        snippet.push_str(&format!(
+            "{:>width$} | {}^ {}\n",
+            "",
+            " ".repeat(offset),
+            self.message,
+            width = width
+        ));
+        snippet
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum InitStatus {
     OK = 0,
@@ -53,10 +122,15 @@ pub enum InitStatus {
 
 #[derive(Debug)]
 pub enum RichFormatError {
-    SyntaxError,
+    SyntaxError(Option<SyntaxErrorLocation>),
     RipperParseFailure(value::Error),
     IOError(std::io::Error),
     OtherRubyError(String),
+    // The input parsed, but not as the kind of fragment it was declared as
+    FragmentKindMismatch(String),
+    // Ruby stopped reading the input before its end, i.e. at a NUL byte, so
+    // the tree is missing everything from this line on
+    IncompleteParse(LineNumber),
 }
 
 impl RichFormatError {
@@ -64,12 +138,44 @@ impl RichFormatError {
         self.as_format_error() as i32
     }
 
+    /// The lines of `source` around where this error happened, with a caret
+    /// under the column Ruby pointed at, for the errors that have a location:
+    ///
+    /// ```
+    /// assert_eq!(rubyfmt::rubyfmt_init(), 0);
+    ///
+    /// let source = "a = 1\nfoo(1,, 2)\n";
+    /// let err = rubyfmt::format_buffer(source).unwrap_err();
+    /// let context = err.source_context(source).unwrap();
+    /// let lines: Vec<&str> = context.lines().collect();
+    ///
+    /// assert_eq!(lines[0], "1 | a = 1");
+    /// assert_eq!(lines[1], "2 | foo(1,, 2)");
+    /// assert_eq!(lines[2].find('^'), Some("2 | foo(1,".len()));
+    /// ```
+    pub fn source_context(&self, source: &str) -> Option<String> {
+        match self {
+            RichFormatError::SyntaxError(Some(location)) => Some(location.snippet(source)),
+            RichFormatError::IncompleteParse(line) => Some(
+                SyntaxErrorLocation {
+                    line: *line,
+                    column: 0,
+                    message: "Ruby stopped reading here".to_string(),
+                }
+                .snippet(source),
+            ),
+            _ => None,
+        }
+    }
+
     fn as_format_error(&self) -> FormatError {
         match self {
-            RichFormatError::SyntaxError => FormatError::SyntaxError,
+            RichFormatError::SyntaxError(_) => FormatError::SyntaxError,
             RichFormatError::RipperParseFailure(_) => FormatError::RipperParseFailure,
             RichFormatError::IOError(_) => FormatError::IOError,
             RichFormatError::OtherRubyError(_) => FormatError::OtherRubyError,
+            RichFormatError::FragmentKindMismatch(_) => FormatError::SyntaxError,
+            RichFormatError::IncompleteParse(_) => FormatError::IncompleteParse,
         }
     }
 }
@@ -83,15 +189,179 @@ pub enum FormatError {
     OtherRubyError = 4,
     // Diffs are only necessary in --check mode
     DiffDetected = 5,
+    IncompleteParse = 6,
 }
 
 pub fn format_buffer(buf: &str) -> Result<String, RichFormatError> {
-    let (tree, file_comments, end_data) = run_parser_on(buf)?;
-    let out_data = vec![];
-    let mut output = Cursor::new(out_data);
-    toplevel_format_program(&mut output, tree, file_comments, end_data)?;
+    format_buffer_with_config(buf, &Config::default())
+}
+
+pub fn format_buffer_with_config(buf: &str, config: &Config) -> Result<String, RichFormatError> {
+    format_buffer_profiled(buf, config).map(|(output, _)| output)
+}
+
+/// Like `format_buffer_with_config`, but also returns how long each phase
+/// of formatting took.
+pub fn format_buffer_profiled(
+    buf: &str,
+    config: &Config,
+) -> Result<(String, Profile), RichFormatError> {
+    format_buffer_reporting(buf, config).map(|(output, profile, _)| (output, profile))
+}
+
+/// Like `format_buffer_with_config`, but also returns the discretionary
+/// changes that were made, such as inserted blank lines or the rewrites that
+/// options like `Config::sort_hash_keys` turn on, at the lines of `buf` they
+/// were made at:
+///
+/// ```
+/// use rubyfmt::{AppliedTransform, Config, MixinSpacing, TransformKind};
+///
+/// assert_eq!(rubyfmt::rubyfmt_init(), 0);
+///
+/// let source = concat!(
+///     "require \"a\"\n",
+///     "foo\n",
+///     "class A\n",
+///     "  include B\n",
+///     "  def x\n",
+///     "  end\n",
+///     "  def y\n",
+///     "  end\n",
+///     "end\n",
+/// );
+/// let config = Config::builder().mixin_spacing(MixinSpacing::Always).build();
+/// let (_, transforms) = rubyfmt::format_with_transforms(source, &config).unwrap();
+///
+/// let transform = |kind, line| AppliedTransform { kind, line };
+/// assert_eq!(
+///     transforms,
+///     vec![
+///         transform(TransformKind::BlankLineAfterRequires, 2),
+///         transform(TransformKind::BlankLineBeforeClassOrModule, 3),
+///         transform(TransformKind::BlankLineBeforeMixin, 4),
+///         transform(TransformKind::BlankLineAfterEnd, 7),
+///     ]
+/// );
+/// ```
+///
+/// The options that rewrite code report each rewrite too:
+///
+/// ```
+/// use rubyfmt::{AppliedTransform, Config, ConditionParens, InlineRescue, TransformKind};
+///
+/// assert_eq!(rubyfmt::rubyfmt_init(), 0);
+///
+/// let path = concat!(
+///     env!("CARGO_MANIFEST_DIR"),
+///     "/../fixtures/options/opt_in_transforms/opt_in_transforms_actual.rb"
+/// );
+/// let source = std::fs::read_to_string(path).unwrap();
+/// let config = Config::builder()
+///     .sort_hash_keys(true)
+///     .align_hash_rows(true)
+///     .condition_parens(ConditionParens::Strip)
+///     .inline_rescue(InlineRescue::Expand)
+///     .build();
+/// let (_, transforms) = rubyfmt::format_with_transforms(&source, &config).unwrap();
+///
+/// let transform = |kind, line| AppliedTransform { kind, line };
+/// assert_eq!(
+///     transforms,
+///     vec![
+///         transform(TransformKind::BlankLineAfterRequires, 2),
+///         transform(TransformKind::SortedHashKeys, 3),
+///         transform(TransformKind::AlignedHashRows, 8),
+///         transform(TransformKind::StrippedConditionParens, 13),
+///         transform(TransformKind::ExpandedInlineRescue, 14),
+///     ]
+/// );
+/// ```
+pub fn format_with_transforms(
+    buf: &str,
+    config: &Config,
+) -> Result<(String, Vec<AppliedTransform>), RichFormatError> {
+    format_buffer_reporting(buf, config).map(|(output, _, transforms)| (output, transforms))
+}
+
+fn format_buffer_reporting(
+    buf: &str,
+    config: &Config,
+) -> Result<(String, Profile, Vec<AppliedTransform>), RichFormatError> {
+    let mut profile = Profile::default();
+    let mut output = Cursor::new(vec![]);
+    let (transforms, final_newlines) = format_into(&mut output, buf, config, &mut profile)?;
     output.flush().expect("flushing to a vec should never fail");
-    Ok(String::from_utf8(output.into_inner()).expect("we never write invalid UTF-8"))
+    let mut output = String::from_utf8(output.into_inner()).expect("we never write invalid UTF-8");
+    if let FinalNewlines::Kept { data_len } = final_newlines {
+        replace_final_newlines(&mut output, buf, data_len);
+    }
+    let transforms = transforms.into_applied(buf, &output);
+    Ok((output, profile, transforms))
+}
+
+/// What happens to the newlines at the end of the code, see
+/// `Config::normalize_final_newline`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum FinalNewlines {
+    /// There's exactly one, which the output already has.
+    Normalized,
+    /// They're replaced with the ones the code in the source ended with.
+    /// When there's a `__END__`, the code ends there, and the data after it
+    /// is `data_len` bytes long in both the source and the output.
+    Kept { data_len: Option<usize> },
+}
+
+/// Formats `buf` into `sink`, also returning what should happen to the
+/// newlines at the end of the output.
+fn format_into<S: TokenSink>(
+    sink: &mut S,
+    buf: &str,
+    config: &Config,
+    profile: &mut Profile,
+) -> Result<(FoundTransforms, FinalNewlines), RichFormatError> {
+    // A file with no code in it stays empty, rather than becoming a newline
+    if buf.trim().is_empty() {
+        return Ok((FoundTransforms::default(), final_newlines(config, None)));
+    }
+
+    let (tree, file_comments, end_data) =
+        timed(&mut profile.parse, || run_parser_with_config(buf, config))?;
+    let final_newlines = final_newlines(config, end_data.map(str::len));
+    let transforms = format_program_into(sink, tree, file_comments, end_data, config, profile)?;
+    Ok((transforms, final_newlines))
+}
+
+fn final_newlines(config: &Config, data_len: Option<usize>) -> FinalNewlines {
+    if config.normalize_final_newline {
+        FinalNewlines::Normalized
+    } else {
+        FinalNewlines::Kept { data_len }
+    }
+}
+
+/// Where the code in `source` ends, which is before the `__END__` line if
+/// there's data after it.
+pub(crate) fn code_end(source: &str, data_len: Option<usize>) -> usize {
+    match data_len {
+        Some(data_len) => {
+            let before_data = &source[..source.len() - data_len];
+            before_data.rfind("__END__").unwrap_or(before_data.len())
+        }
+        None => source.len(),
+    }
+}
+
+/// Replaces the newlines at the end of the code in `output` with the ones at
+/// the end of the code in `source`. Anything after a `__END__` is copied over
+/// verbatim, so it's already the same in both.
+pub(crate) fn replace_final_newlines(output: &mut String, source: &str, data_len: Option<usize>) {
+    let is_newline = |c| c == '\n' || c == '\r';
+    let data = output.split_off(code_end(output, data_len));
+    let source = &source[..code_end(source, data_len)];
+    output.truncate(output.trim_end_matches(is_newline).len());
+    output.push_str(&source[source.trim_end_matches(is_newline).len()..]);
+    output.push_str(&data);
 }
 
 #[no_mangle]
@@ -198,36 +468,93 @@ pub fn toplevel_format_program<W: Write>(
     tree: RipperTree,
     file_comments: FileComments,
     end_data: Option<&str>,
+    config: &Config,
+    profile: &mut Profile,
 ) -> Result<(), RichFormatError> {
-    let mut ps = BaseParserState::new(file_comments);
-    let v: ripper_tree_types::Program =
-        de::from_value(tree).map_err(RichFormatError::RipperParseFailure)?;
-
-    format::format_program(&mut ps, v, end_data);
-
-    ps.write(writer).map_err(RichFormatError::IOError)?;
+    format_program_into(writer, tree, file_comments, end_data, config, profile)?;
     writer.flush().map_err(RichFormatError::IOError)?;
     Ok(())
 }
 
+fn format_program_into<S: TokenSink>(
+    sink: &mut S,
+    tree: RipperTree,
+    file_comments: FileComments,
+    end_data: Option<&str>,
+    config: &Config,
+    profile: &mut Profile,
+) -> Result<FoundTransforms, RichFormatError> {
+    let mut ps = BaseParserState::new(file_comments, *config);
+    let v: ripper_tree_types::Program = timed(&mut profile.parse, || de::from_value(tree))
+        .map_err(RichFormatError::RipperParseFailure)?;
+
+    timed(&mut profile.format, || {
+        format::format_program(&mut ps, v, end_data)
+    });
+
+    ps.write(sink, profile).map_err(RichFormatError::IOError)
+}
+
 fn run_parser_on(buf: &str) -> Result<(RipperTree, FileComments, Option<&str>), RichFormatError> {
-    Parser::new(buf).parse().map_err(|e| match e {
-        ParseError::SyntaxError => RichFormatError::SyntaxError,
+    run_parser_with_config(buf, &Config::default())
+}
+
+pub(crate) fn run_parser_with_config<'a>(
+    buf: &'a str,
+    config: &Config,
+) -> Result<(RipperTree, FileComments, Option<&'a str>), RichFormatError> {
+    let parse = || {
+        let mut parser = Parser::new(buf);
+        if !config.normalize_strings {
+            parser = parser.preserve_strings()?;
+        }
+        if config.comment_indent == CommentIndent::Keep {
+            parser = parser.keep_comment_indents()?;
+        }
+        parser.parse()
+    };
+    parse().map_err(|e| match e {
+        ParseError::SyntaxError(location) => RichFormatError::SyntaxError(location),
+        ParseError::IncompleteParse(line) => RichFormatError::IncompleteParse(line),
         ParseError::OtherRubyError(s) => RichFormatError::OtherRubyError(s),
     })
 }
 
+#[cfg(debug_assertions)]
+const DEFAULT_LOG_LEVEL: LevelFilter = LevelFilter::Debug;
+#[cfg(not(debug_assertions))]
+const DEFAULT_LOG_LEVEL: LevelFilter = LevelFilter::Warn;
+
 fn init_logger() {
+    // The logger itself accepts everything, the global max level decides
+    // what actually gets logged so that it can be raised after init.
+    TermLogger::init(
+        LevelFilter::Trace,
+        LogConfigBuilder::new()
+            .set_time_level(LevelFilter::Off)
+            .build(),
+        TerminalMode::Stderr,
+    )
+    .expect("making a term logger");
+    log::set_max_level(DEFAULT_LOG_LEVEL);
     #[cfg(debug_assertions)]
     {
-        TermLogger::init(
-            LevelFilter::Debug,
-            ConfigBuilder::new()
-                .set_time_level(LevelFilter::Off)
-                .build(),
-            TerminalMode::Stderr,
-        )
-        .expect("making a term logger");
         debug!("logger works");
     }
 }
+
+/// Logs which files were formatted and why blank lines were inserted
+/// to stderr, unless the log level is already more verbose than that.
+pub fn enable_verbose_logging() {
+    if log::max_level() < LevelFilter::Info {
+        set_log_level(LevelFilter::Info);
+    }
+}
+
+/// Sets the most verbose level that gets logged to stderr. It's `Warn` by
+/// default in release builds, `Info` adds which files were formatted and
+/// why blank lines were inserted, and `Debug` traces the formatter itself.
+/// Call this after `rubyfmt_init`, which sets the default.
+pub fn set_log_level(level: LevelFilter) {
+    log::set_max_level(level);
+}
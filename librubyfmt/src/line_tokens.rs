@@ -110,6 +110,8 @@ impl ConcreteLineToken {
     fn is_conditional_spaced_token(&self) -> bool {
         match self {
             Self::ConditionalKeyword { contents } => !(contents == "else" || contents == "elsif"),
+            // These continue the body that the `end` before them is in
+            Self::Keyword { keyword } => !(keyword == "rescue" || keyword == "ensure"),
             Self::Dot => false,
             Self::DirectPart { part } => part != "&.",
             _ => true,
@@ -0,0 +1,163 @@
+//! Formatting the Ruby code blocks in a Markdown document, for docs tooling.
+
+use crate::config::Config;
+use crate::{format_buffer_with_config, RichFormatError};
+
+/// A fenced Ruby block which couldn't be formatted, and so was left as it
+/// was.
+#[derive(Debug)]
+pub struct MarkdownBlockError {
+    /// The line of the block's opening fence, starting from 1.
+    pub line: usize,
+    pub error: RichFormatError,
+}
+
+/// Formats the code in every fenced block of `buf` tagged `ruby` or `rb`,
+/// leaving everything else untouched. Blocks that fail to format are left
+/// as they were, and reported alongside the output:
+///
+/// ```
+/// assert_eq!(rubyfmt::rubyfmt_init(), 0);
+///
+/// let doc = concat!(
+///     "# Usage\n",
+///     "```ruby\n",
+///     "puts 'hello'\n",
+///     "```\n",
+///     "\n",
+///     "```sh\n",
+///     "echo 'untouched'\n",
+///     "```\n",
+///     "\n",
+///     "```ruby\n",
+///     "def broken(\n",
+///     "```\n",
+/// );
+/// let (output, errors) = rubyfmt::format_markdown(doc, &rubyfmt::Config::default());
+///
+/// assert_eq!(
+///     output,
+///     doc.replace("puts 'hello'", "puts(\"hello\")"),
+/// );
+/// assert_eq!(errors.len(), 1);
+/// assert_eq!(errors[0].line, 10);
+/// ```
+pub fn format_markdown(buf: &str, config: &Config) -> (String, Vec<MarkdownBlockError>) {
+    let mut output = String::with_capacity(buf.len());
+    let mut errors = Vec::new();
+    let mut lines = buf.split_inclusive('\n').enumerate();
+
+    while let Some((idx, line)) = lines.next() {
+        output.push_str(line);
+        let fence = match Fence::open(line) {
+            Some(fence) => fence,
+            None => continue,
+        };
+
+        let mut body = Vec::new();
+        let mut closing = None;
+        for (_, line) in lines.by_ref() {
+            if fence.is_closed_by(line) {
+                closing = Some(line);
+                break;
+            }
+            body.push(line);
+        }
+
+        // An unclosed fence runs to the end of the document, which is more
+        // likely to be a mistake than Ruby, so leave it alone
+        let closing = match closing {
+            Some(closing) => closing,
+            None => {
+                body.iter().for_each(|l| output.push_str(l));
+                break;
+            }
+        };
+
+        if fence.is_ruby && !body.iter().all(|l| l.trim().is_empty()) {
+            match format_block(&body, fence.indent, config) {
+                Ok(formatted) => output.push_str(&formatted),
+                Err(error) => {
+                    errors.push(MarkdownBlockError {
+                        line: idx + 1,
+                        error,
+                    });
+                    body.iter().for_each(|l| output.push_str(l));
+                }
+            }
+        } else {
+            body.iter().for_each(|l| output.push_str(l));
+        }
+        output.push_str(closing);
+    }
+
+    (output, errors)
+}
+
+/// A CommonMark code fence, i.e. ```` ```ruby ```` or `~~~`.
+struct Fence {
+    marker: char,
+    len: usize,
+    indent: usize,
+    is_ruby: bool,
+}
+
+impl Fence {
+    fn open(line: &str) -> Option<Fence> {
+        let trimmed = line.trim_start_matches(' ');
+        let indent = line.len() - trimmed.len();
+        let marker = trimmed.chars().next()?;
+        if indent > 3 || (marker != '`' && marker != '~') {
+            return None;
+        }
+
+        let len = trimmed.chars().take_while(|c| *c == marker).count();
+        if len < 3 {
+            return None;
+        }
+
+        let info = trimmed[len..].trim();
+        // Backticks in the info string make this inline code, not a fence
+        if marker == '`' && info.contains('`') {
+            return None;
+        }
+
+        let language = info.split_whitespace().next().unwrap_or("");
+        Some(Fence {
+            marker,
+            len,
+            indent,
+            is_ruby: matches!(language, "ruby" | "rb"),
+        })
+    }
+
+    fn is_closed_by(&self, line: &str) -> bool {
+        let trimmed = line.trim_start_matches(' ');
+        let len = trimmed.chars().take_while(|c| *c == self.marker).count();
+        line.len() - trimmed.len() <= 3 && len >= self.len && trimmed[len..].trim().is_empty()
+    }
+}
+
+/// Formats the lines of a block, which are indented to the same level as
+/// their fence.
+fn format_block(body: &[&str], indent: usize, config: &Config) -> Result<String, RichFormatError> {
+    let source: String = body.iter().map(|l| strip_indent(l, indent)).collect();
+    let formatted = format_buffer_with_config(&source, config)?;
+
+    let prefix = " ".repeat(indent);
+    Ok(formatted
+        .split_inclusive('\n')
+        .map(|l| {
+            if l.trim().is_empty() {
+                l.to_string()
+            } else {
+                format!("{}{}", prefix, l)
+            }
+        })
+        .collect())
+}
+
+fn strip_indent(line: &str, indent: usize) -> &str {
+    let spaces = line.len() - line.trim_start_matches(' ').len();
+    &line[spaces.min(indent)..]
+}
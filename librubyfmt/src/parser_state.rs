@@ -1,15 +1,20 @@
 use crate::comment_block::{CommentBlock, Merge};
+use crate::config::Config;
 use crate::delimiters::BreakableDelims;
 use crate::file_comments::FileComments;
 use crate::format::{format_inner_string, StringType};
 use crate::heredoc_string::{HeredocKind, HeredocString};
 use crate::line_tokens::*;
-use crate::render_queue_writer::{RenderQueueWriter, MAX_LINE_LENGTH};
+use crate::profile::Profile;
+use crate::render_queue_writer::{RenderQueueWriter, TokenSink, MAX_LINE_LENGTH};
 use crate::render_targets::{AbstractTokenTarget, BaseQueue, BreakableEntry};
 use crate::ripper_tree_types::StringContentPart;
+use crate::transforms::{AppliedTransform, FoundTransforms, TransformKind};
 use crate::types::{ColNumber, LineNumber};
 use log::debug;
-use std::io::{self, Cursor, Write};
+use std::io::{self, Cursor};
+use std::mem;
+use std::ops::Range;
 use std::str;
 
 pub type RenderFunc<'a> = Box<dyn FnOnce(&mut dyn ConcreteParserState) + 'a>;
@@ -27,14 +32,21 @@ pub enum FormattingContext {
     StringEmbexpr,
 }
 
+// Block indents (bodies of `def`, `class`, `if` etc.) and continuation
+// indents (broken collections, argument lists and call chains) are counted
+// separately, since continuations can be configured to be a different width
 #[derive(Debug, Clone, Copy)]
 struct IndentDepth {
     depth: ColNumber,
+    continuations: ColNumber,
 }
 
 impl IndentDepth {
     fn new() -> Self {
-        IndentDepth { depth: 0 }
+        IndentDepth {
+            depth: 0,
+            continuations: 0,
+        }
     }
 
     fn increment(&mut self) {
@@ -45,8 +57,16 @@ impl IndentDepth {
         self.depth -= 1;
     }
 
-    fn get(self) -> u32 {
-        self.depth
+    fn increment_continuation(&mut self) {
+        self.continuations += 1;
+    }
+
+    fn decrement_continuation(&mut self) {
+        self.continuations -= 1;
+    }
+
+    fn spaces(self, continuation_indent: ColNumber) -> ColNumber {
+        2 * self.depth + continuation_indent * self.continuations
     }
 }
 
@@ -102,10 +122,15 @@ where
     fn scope_has_variable(&self, s: &str) -> bool;
     fn insert_comment_collection(&mut self, comments: CommentBlock);
     fn on_line(&mut self, line_number: LineNumber);
+    fn jump_to_line(&mut self, line_number: LineNumber);
+    fn take_comments_in_lines(&mut self, lines: Range<LineNumber>) -> Option<CommentBlock>;
+    fn record_transform(&mut self, kind: TransformKind, line: LineNumber);
     fn wind_dumping_comments_until_line(&mut self, line_number: LineNumber);
     fn wind_dumping_comments(&mut self, maybe_max_line_number: Option<LineNumber>);
     fn shift_comments(&mut self);
     fn shift_comments_at_index(&mut self, index: usize);
+    fn take_trailing_comment(&mut self, before_line: LineNumber) -> Option<TrailingComment>;
+    fn emit_trailing_comment(&mut self, comment: TrailingComment);
     fn wind_line_forward(&mut self);
     fn render_heredocs(&mut self, skip: bool);
     fn push_heredoc_content(
@@ -122,7 +147,10 @@ where
     fn current_formatting_context(&self) -> FormattingContext;
     fn is_absorbing_indents(&self) -> bool;
     fn has_comments_in_line(&self, start_line: LineNumber, end_line: LineNumber) -> bool;
+    fn has_ruby_in_lines(&self, start_line: LineNumber, end_line: LineNumber) -> bool;
     fn current_line_number(&self) -> u64;
+    fn config(&self) -> Config;
+    fn current_spaces(&self) -> ColNumber;
 
     // blocks
     fn start_indent(&mut self);
@@ -145,6 +173,7 @@ where
     fn will_render_as_multiline(&mut self, f: RenderFunc) -> bool;
 
     fn will_render_beyond_max_line_length(&mut self, f: RenderFunc) -> bool;
+    fn rendered_width(&mut self, f: RenderFunc) -> usize;
 
     // stuff to remove from this enum
     fn emit_soft_newline(&mut self);
@@ -152,6 +181,14 @@ where
     fn emit_collapsing_newline(&mut self);
 }
 
+/// A comment at the end of a line of code, which stays at the end of that
+/// line instead of moving above it.
+#[derive(Debug, Clone)]
+pub struct TrailingComment {
+    line: LineNumber,
+    contents: String,
+}
+
 #[derive(Debug)]
 pub struct BaseParserState {
     depth_stack: Vec<IndentDepth>,
@@ -168,9 +205,21 @@ pub struct BaseParserState {
     insert_user_newlines: bool,
     spaces_after_last_newline: ColNumber,
     scopes: Vec<Vec<String>>,
+    transforms: Vec<AppliedTransform>,
+    config: Config,
 }
 
 impl ConcreteParserState for BaseParserState {
+    fn config(&self) -> Config {
+        self.config
+    }
+
+    fn current_spaces(&self) -> ColNumber {
+        self.depth_stack
+            .last()
+            .expect("depth stack is never empty")
+            .spaces(self.config.continuation_indent)
+    }
     fn scope_has_variable(&self, s: &str) -> bool {
         self.scopes
             .last()
@@ -274,6 +323,18 @@ impl ConcreteParserState for BaseParserState {
         s.trim().contains('\n')
     }
 
+    fn rendered_width(&mut self, f: RenderFunc) -> usize {
+        let mut next_ps = BaseParserState::new_with_depth_stack_from(self);
+        next_ps.with_suppress_comments(true, f);
+        let data = next_ps.render_to_buffer();
+
+        str::from_utf8(&data)
+            .expect("string is utf8")
+            .trim()
+            .chars()
+            .count()
+    }
+
     fn will_render_beyond_max_line_length<'a>(&mut self, f: RenderFunc) -> bool {
         let mut next_ps = BaseParserState::new_with_depth_stack_from(self);
         // Ignore commments when determining line length
@@ -300,12 +361,12 @@ impl ConcreteParserState for BaseParserState {
 
     fn start_indent(&mut self) {
         let ds_length = self.depth_stack.len();
-        self.depth_stack[ds_length - 1].increment();
+        self.depth_stack[ds_length - 1].increment_continuation();
     }
 
     fn end_indent(&mut self) {
         let ds_length = self.depth_stack.len();
-        self.depth_stack[ds_length - 1].decrement();
+        self.depth_stack[ds_length - 1].decrement_continuation();
     }
 
     fn with_start_of_line<'a>(&mut self, start_of_line: bool, f: RenderFunc) {
@@ -324,7 +385,7 @@ impl ConcreteParserState for BaseParserState {
         be.push_line_number(self.current_orig_line_number);
         self.breakable_entry_stack.push(Box::new(be));
 
-        self.new_block(Box::new(|ps| {
+        self.new_continuation_block(Box::new(|ps| {
             ps.emit_collapsing_newline();
             f(ps);
             ps.emit_collapsing_newline();
@@ -362,7 +423,7 @@ impl ConcreteParserState for BaseParserState {
         be.push_line_number(self.current_orig_line_number);
         self.breakable_entry_stack.push(Box::new(be));
 
-        self.new_block(Box::new(|ps| {
+        self.new_continuation_block(Box::new(|ps| {
             ps.emit_collapsing_newline();
             f(ps);
         }));
@@ -444,6 +505,30 @@ impl ConcreteParserState for BaseParserState {
         );
     }
 
+    /// Moves on to `line_number` without inserting the comments or blank
+    /// lines before it, for code that's formatted in a different order than
+    /// it was written in. Its comments have to be taken out beforehand with
+    /// `take_comments_in_lines`.
+    fn jump_to_line(&mut self, line_number: LineNumber) {
+        for be in self.breakable_entry_stack.iter_mut().rev() {
+            be.push_line_number(line_number);
+        }
+        self.current_orig_line_number = std::cmp::max(self.current_orig_line_number, line_number);
+    }
+
+    fn take_comments_in_lines(&mut self, lines: Range<LineNumber>) -> Option<CommentBlock> {
+        let comments = self.comments_hash.take_comments_in_lines(lines.clone());
+        if comments.is_empty() {
+            None
+        } else {
+            Some(CommentBlock::new(lines, comments))
+        }
+    }
+
+    fn record_transform(&mut self, kind: TransformKind, line: LineNumber) {
+        self.transforms.push(AppliedTransform { kind, line });
+    }
+
     fn emit_indent(&mut self) {
         self.push_concrete_token(ConcreteLineToken::Indent {
             depth: self.current_spaces(),
@@ -462,6 +547,10 @@ impl ConcreteParserState for BaseParserState {
             .has_comments_in_lines(start_line, end_line)
     }
 
+    fn has_ruby_in_lines(&self, start_line: LineNumber, end_line: LineNumber) -> bool {
+        (start_line..end_line).any(|line| self.comments_hash.has_ruby_on_line(line))
+    }
+
     fn current_line_number(&self) -> u64 {
         self.current_orig_line_number
     }
@@ -568,10 +657,12 @@ impl ConcreteParserState for BaseParserState {
     }
 
     fn emit_end(&mut self) {
-        if !self.last_token_is_a_newline() {
-            self.emit_newline();
-        }
+        // An `end` that isn't at the start of a line stays on the line it's
+        // on, i.e. in a compact `class Foo; end`
         if self.at_start_of_line() {
+            if !self.last_token_is_a_newline() {
+                self.emit_newline();
+            }
             self.emit_indent();
         }
         self.push_concrete_token(ConcreteLineToken::End);
@@ -595,6 +686,37 @@ impl ConcreteParserState for BaseParserState {
         }
     }
 
+    fn take_trailing_comment(&mut self, before_line: LineNumber) -> Option<TrailingComment> {
+        if self
+            .formatting_context
+            .contains(&FormattingContext::StringEmbexpr)
+        {
+            return None;
+        }
+        let line = self.comments_hash.last_ruby_line_before(before_line)?;
+        if line < self.current_orig_line_number {
+            return None;
+        }
+        let contents = self.comments_hash.take_comment_on_line(line)?;
+        Some(TrailingComment { line, contents })
+    }
+
+    fn emit_trailing_comment(&mut self, comment: TrailingComment) {
+        if self.current_orig_line_number == comment.line {
+            self.emit_space();
+            self.push_concrete_token(ConcreteLineToken::Comment {
+                contents: comment.contents,
+            });
+        } else {
+            // The code was moved off the line, so the comment goes above it
+            // like any other
+            self.insert_comment_collection(CommentBlock::new(
+                comment.line..comment.line + 1,
+                vec![comment.contents],
+            ));
+        }
+    }
+
     fn emit_soft_newline(&mut self) {
         self.new_block(Box::new(|ps| {
             ps.shift_comments();
@@ -811,7 +933,7 @@ impl ConcreteParserState for BaseParserState {
 }
 
 impl BaseParserState {
-    pub fn new(fc: FileComments) -> Self {
+    pub fn new(fc: FileComments, config: Config) -> Self {
         BaseParserState {
             depth_stack: vec![IndentDepth::new()],
             start_of_line: vec![true],
@@ -827,6 +949,8 @@ impl BaseParserState {
             insert_user_newlines: true,
             spaces_after_last_newline: 0,
             scopes: vec![vec![]],
+            transforms: vec![],
+            config,
         }
     }
 
@@ -888,12 +1012,10 @@ impl BaseParserState {
         }
     }
 
-    fn current_spaces(&self) -> ColNumber {
-        2 * self
-            .depth_stack
-            .last()
-            .expect("depth stack is never empty")
-            .get()
+    fn new_continuation_block(&mut self, f: RenderFunc) {
+        self.start_indent();
+        f(self);
+        self.end_indent();
     }
 
     fn last_token_is_a_newline(&self) -> bool {
@@ -911,7 +1033,7 @@ impl BaseParserState {
     }
 
     fn new_with_depth_stack_from(ps: &BaseParserState) -> Self {
-        let mut next_ps = BaseParserState::new(FileComments::default());
+        let mut next_ps = BaseParserState::new(FileComments::default(), ps.config);
         next_ps.comments_hash = ps.comments_hash.clone();
         next_ps.start_of_line = ps.start_of_line.clone();
         next_ps.depth_stack = ps.depth_stack.clone();
@@ -921,14 +1043,22 @@ impl BaseParserState {
 
     fn render_to_buffer(self) -> Vec<u8> {
         let mut bufio = Cursor::new(Vec::new());
-        self.write(&mut bufio).expect("in memory io cannot fail");
+        self.write(&mut bufio, &mut Profile::default())
+            .expect("in memory io cannot fail");
         bufio.set_position(0);
         bufio.into_inner()
     }
 
-    pub fn write<W: Write>(self, writer: &mut W) -> io::Result<()> {
-        let rqw = RenderQueueWriter::new(self.consume_to_render_queue());
-        rqw.write(writer)
+    pub fn write<S: TokenSink>(
+        mut self,
+        sink: &mut S,
+        profile: &mut Profile,
+    ) -> io::Result<FoundTransforms> {
+        let config = self.config;
+        let applied = mem::take(&mut self.transforms);
+        let rqw = RenderQueueWriter::new(self.consume_to_render_queue(), config);
+        let rendered = rqw.write(sink, profile)?;
+        Ok(FoundTransforms { applied, rendered })
     }
 
     fn dangerously_convert(t: AbstractLineToken) -> ConcreteLineTokenAndTargets {
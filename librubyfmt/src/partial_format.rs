@@ -0,0 +1,378 @@
+//! Best-effort formatting of part of a file, for editor integrations.
+//!
+//! A file is split into chunks: runs of top-level statements that are
+//! separated from the next run by a blank line, along with any comments
+//! directly above them. rubyfmt always keeps that blank line, so a chunk can
+//! be formatted on its own without affecting its neighbours.
+
+use std::ops::Range;
+
+use similar::{capture_diff_slices, Algorithm};
+
+use crate::config::Config;
+use crate::file_comments::FileComments;
+use crate::ruby::{self, ruby_value_type, VALUE};
+use crate::ruby_ops::RipperTree;
+use crate::types::LineNumber;
+use crate::{format_buffer_with_config, run_parser_with_config, RichFormatError};
+
+/// A change to a source buffer: the bytes in `range` are replaced with
+/// `replacement`.
+#[derive(Debug, Clone)]
+pub struct SourceEdit {
+    pub range: Range<usize>,
+    pub replacement: String,
+}
+
+/// Formats the lines of `buf` in `lines` with `config`, leaving the rest of
+/// the buffer as it is. `lines` is zero indexed and end exclusive, like an editor
+/// selection.
+///
+/// The selection doesn't have to be valid Ruby on its own, i.e. it can be
+/// part of a block's body. The top-level constructs that overlap it are
+/// formatted as a whole, and just the selected lines of that are spliced
+/// back in, so they come out exactly as they would when formatting the
+/// whole file. When formatting joins or splits lines across the edge of the
+/// selection, all of them are replaced.
+///
+/// ```
+/// use rubyfmt::Config;
+///
+/// assert_eq!(rubyfmt::rubyfmt_init(), 0);
+///
+/// let source = "def foo\n  [1,2].each do |x|\n   puts x\n  end\nend\n";
+/// // Only the body of the block
+/// let formatted = rubyfmt::format_range(source, 2..3, &Config::default()).unwrap();
+/// assert_eq!(formatted, "def foo\n  [1,2].each do |x|\n    puts(x)\n  end\nend\n");
+///
+/// let whole_file = rubyfmt::format_buffer(source).unwrap();
+/// assert_eq!(formatted.lines().nth(2), whole_file.lines().nth(2));
+/// ```
+pub fn format_range(
+    buf: &str,
+    lines: Range<usize>,
+    config: &Config,
+) -> Result<String, RichFormatError> {
+    let chunks = chunk_buffer(buf, config)?;
+    let line_starts = line_starts(buf);
+    let selected_byte_range = line_offset(&line_starts, buf, lines.start)
+        ..line_offset(&line_starts, buf, lines.end.max(lines.start + 1));
+
+    let first = chunks
+        .iter()
+        .position(|c| c.end > selected_byte_range.start)
+        .unwrap_or(chunks.len());
+    let last = chunks
+        .iter()
+        .rposition(|c| c.start < selected_byte_range.end)
+        .map(|idx| idx + 1)
+        .unwrap_or(0);
+    if first >= last {
+        return Ok(buf.to_string());
+    }
+
+    // Everything outside of the selected chunks is left as it is, so the
+    // formatted chunks are what's between the unchanged ends
+    let construct = chunks[first].start..chunks[last - 1].end;
+    let formatted = format_chunks(buf, &chunks, first..last, config)?;
+    let formatted_construct =
+        &formatted[construct.start..formatted.len() - (buf.len() - construct.end)];
+
+    let old_lines: Vec<&str> = buf[construct.clone()].split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = formatted_construct.split_inclusive('\n').collect();
+    let first_line = line_starts.partition_point(|start| *start < construct.start);
+    let selected = lines.start.saturating_sub(first_line)
+        ..lines
+            .end
+            .max(lines.start + 1)
+            .saturating_sub(first_line)
+            .min(old_lines.len());
+    let (old_selected, new_selected) = formatted_selection(&old_lines, &new_lines, selected);
+
+    let mut output = String::with_capacity(formatted.len());
+    output.push_str(&buf[..construct.start]);
+    output.extend(old_lines[..old_selected.start].iter().copied());
+    output.extend(new_lines[new_selected].iter().copied());
+    output.extend(old_lines[old_selected.end..].iter().copied());
+    output.push_str(&buf[construct.end..]);
+    Ok(output)
+}
+
+/// Finds the lines of `new` that the `selected` lines of `old` were
+/// formatted into. Changes that cross the edges of the selection are taken
+/// whole, so the selection in `old` that they replace is returned as well.
+fn formatted_selection(
+    old: &[&str],
+    new: &[&str],
+    selected: Range<usize>,
+) -> (Range<usize>, Range<usize>) {
+    // Pairs of ranges of `old` and `new` that are the same code
+    let mut segments: Vec<(Range<usize>, Range<usize>)> = Vec::new();
+    for op in capture_diff_slices(Algorithm::Myers, old, new) {
+        let (_, old_range, new_range) = op.as_tag_tuple();
+        if old_range.len() == new_range.len() {
+            // Unchanged lines, or lines that were reformatted one by one
+            segments.extend(old_range.zip(new_range).map(|(o, n)| (o..o + 1, n..n + 1)));
+        } else {
+            segments.push((old_range, new_range));
+        }
+    }
+
+    let (old_start, new_start) = segments
+        .iter()
+        .find(|(o, _)| o.end > selected.start)
+        .map(|(o, n)| (o.start, n.start))
+        .unwrap_or((old.len(), new.len()));
+    let (old_end, new_end) = segments
+        .iter()
+        .rev()
+        .find(|(o, _)| !o.is_empty() && o.start < selected.end)
+        .map(|(o, n)| (o.end, n.end))
+        .unwrap_or((old_start, new_start));
+    (old_start..old_end, new_start..new_end)
+}
+
+/// Experimental: formats `previous_source` with `edit` applied, given that
+/// `previous_formatted` is `previous_source` formatted with `config`.
+///
+/// When the edit is contained in a single top-level construct only that
+/// construct is reformatted, and the rest of the output is reused from
+/// `previous_formatted`. Otherwise this falls back to formatting the whole
+/// edited buffer. Either way the output is the same as formatting the whole
+/// edited buffer:
+///
+/// ```
+/// use rubyfmt::{Config, SourceEdit};
+///
+/// assert_eq!(rubyfmt::rubyfmt_init(), 0);
+///
+/// let config = Config::default();
+/// let source = "a = 1\n\ndef foo\n  bar(1,\n      2)\nend\n\nb = 2\n";
+/// let formatted = rubyfmt::format_buffer_with_config(source, &config).unwrap();
+///
+/// let insert = |at: &str, replacement: &str| {
+///     let start = source.find(at).unwrap();
+///     SourceEdit { range: start..start, replacement: replacement.to_string() }
+/// };
+/// let replace = |old: &str, replacement: &str| {
+///     let start = source.find(old).unwrap();
+///     SourceEdit { range: start..start + old.len(), replacement: replacement.to_string() }
+/// };
+/// let edits = vec![
+///     // Inserting a statement into the method
+///     insert("end", "  baz  3\n"),
+///     // Deleting the second argument of the call
+///     replace("      2)", ")"),
+///     // Editing an argument in the middle of the call that spans two lines
+///     replace("1,\n", "[1,2] ,\n"),
+///     // Editing a statement in another top-level construct
+///     replace("b = 2", "b=3"),
+/// ];
+///
+/// for edit in edits {
+///     let mut edited = source.to_string();
+///     edited.replace_range(edit.range.clone(), &edit.replacement);
+///     assert_eq!(
+///         rubyfmt::format_incremental(source, &formatted, &edit, &config).unwrap(),
+///         rubyfmt::format_buffer_with_config(&edited, &config).unwrap(),
+///     );
+/// }
+///
+/// // Edits that split a top-level construct in two are formatted as a whole
+/// let edit = insert("  bar", "end\n\ndef qux\n");
+/// let mut edited = source.to_string();
+/// edited.replace_range(edit.range.clone(), &edit.replacement);
+/// assert_eq!(
+///     rubyfmt::format_incremental(source, &formatted, &edit, &config).unwrap(),
+///     rubyfmt::format_buffer_with_config(&edited, &config).unwrap(),
+/// );
+/// ```
+///
+/// # Panics
+///
+/// Panics if `edit.range` is out of bounds, or doesn't lie on `char`
+/// boundaries of `previous_source`.
+pub fn format_incremental(
+    previous_source: &str,
+    previous_formatted: &str,
+    edit: &SourceEdit,
+    config: &Config,
+) -> Result<String, RichFormatError> {
+    let mut source = previous_source.to_string();
+    source.replace_range(edit.range.clone(), &edit.replacement);
+
+    match format_localized_edit(previous_source, previous_formatted, &source, edit, config) {
+        Some(formatted) => Ok(formatted),
+        None => format_buffer_with_config(&source, config),
+    }
+}
+
+fn format_localized_edit(
+    previous_source: &str,
+    previous_formatted: &str,
+    source: &str,
+    edit: &SourceEdit,
+    config: &Config,
+) -> Option<String> {
+    let old_chunks = chunk_buffer(previous_source, config).ok()?;
+    let new_chunks = chunk_buffer(source, config).ok()?;
+    let formatted_chunks = chunk_buffer(previous_formatted, config).ok()?;
+
+    if old_chunks.len() != new_chunks.len() || old_chunks.len() != formatted_chunks.len() {
+        return None;
+    }
+
+    let edited = old_chunks
+        .iter()
+        .position(|c| c.start <= edit.range.start && edit.range.end <= c.end)?;
+
+    // The edit must not have moved any chunk boundaries, every other chunk
+    // has to be exactly as it was before.
+    let others_unchanged = old_chunks
+        .iter()
+        .zip(new_chunks.iter())
+        .enumerate()
+        .filter(|(idx, _)| *idx != edited)
+        .all(|(_, (old, new))| previous_source[old.clone()] == source[new.clone()]);
+    if !others_unchanged {
+        return None;
+    }
+
+    let mut buf = String::with_capacity(previous_formatted.len());
+    let mut chunks = Vec::with_capacity(formatted_chunks.len());
+    for (idx, formatted_chunk) in formatted_chunks.into_iter().enumerate() {
+        let start = buf.len();
+        if idx == edited {
+            buf.push_str(&source[new_chunks[idx].clone()]);
+        } else {
+            buf.push_str(&previous_formatted[formatted_chunk]);
+        }
+        chunks.push(start..buf.len());
+    }
+
+    format_chunks(&buf, &chunks, edited..edited + 1, config).ok()
+}
+
+fn format_chunks(
+    buf: &str,
+    chunks: &[Range<usize>],
+    selected: Range<usize>,
+    config: &Config,
+) -> Result<String, RichFormatError> {
+    let mut output = String::with_capacity(buf.len());
+    for (idx, chunk) in chunks.iter().enumerate() {
+        let text = &buf[chunk.clone()];
+        if selected.contains(&idx) {
+            output.push_str(&format_buffer_with_config(text, config)?);
+            // Formatting trims the blank line that separates this chunk
+            // from the next one, so put it back. Only the last chunk's final
+            // newlines are the file's, which might be kept as they are.
+            if idx + 1 != chunks.len() {
+                output.truncate(output.trim_end_matches('\n').len());
+                output.push_str("\n\n");
+            }
+        } else {
+            output.push_str(text);
+        }
+    }
+    Ok(output)
+}
+
+/// Splits `buf` into byte ranges that cover the whole buffer, one per chunk.
+fn chunk_buffer(buf: &str, config: &Config) -> Result<Vec<Range<usize>>, RichFormatError> {
+    let (tree, file_comments, _) = run_parser_with_config(buf, config)?;
+    let line_starts = line_starts(buf);
+    let line_text = |line: LineNumber| {
+        let idx = (line - 1) as usize;
+        &buf[line_starts[idx]..line_offset(&line_starts, buf, idx + 1)]
+    };
+
+    let mut chunk_start_lines: Vec<LineNumber> = Vec::new();
+    for statement_line in top_level_statement_lines(tree) {
+        if statement_line as usize > line_starts.len() {
+            continue;
+        }
+
+        let mut line = statement_line;
+        while line > 1 && is_comment_line(&file_comments, line - 1, line_text(line - 1)) {
+            line -= 1;
+        }
+
+        let follows_blank_line = line > 1 && line_text(line - 1).trim().is_empty();
+        let after_last_chunk = chunk_start_lines.last().map(|l| *l < line).unwrap_or(true);
+        if follows_blank_line && after_last_chunk {
+            chunk_start_lines.push(line);
+        }
+    }
+
+    let mut starts = vec![0];
+    starts.extend(
+        chunk_start_lines
+            .into_iter()
+            .map(|line| line_starts[(line - 1) as usize]),
+    );
+    let ends = starts.iter().skip(1).copied().chain(Some(buf.len()));
+    Ok(starts
+        .iter()
+        .copied()
+        .zip(ends)
+        .map(|(s, e)| s..e)
+        .collect())
+}
+
+fn is_comment_line(file_comments: &FileComments, line: LineNumber, text: &str) -> bool {
+    // Checking the text as well rules out trailing comments, which would
+    // make this line part of the previous statement
+    file_comments.has_line(line) && text.trim_start().starts_with('#')
+}
+
+pub(crate) fn line_starts(buf: &str) -> Vec<usize> {
+    let mut starts = vec![0];
+    starts.extend(buf.match_indices('\n').map(|(idx, _)| idx + 1));
+    starts
+}
+
+pub(crate) fn line_offset(line_starts: &[usize], buf: &str, line: usize) -> usize {
+    line_starts.get(line).copied().unwrap_or(buf.len())
+}
+
+/// The first line of each top-level statement in a `[:program, [stmts]]`
+/// tree, found from the positions of the tokens inside it.
+pub(crate) fn top_level_statement_lines(tree: RipperTree) -> Vec<LineNumber> {
+    unsafe {
+        match ruby::ruby_array_to_slice(tree.into_value()) {
+            [_, statements] if is_array(*statements) => ruby::ruby_array_to_slice(*statements)
+                .iter()
+                .filter_map(|s| first_line_in(*s))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+unsafe fn first_line_in(v: VALUE) -> Option<LineNumber> {
+    if !is_array(v) {
+        return None;
+    }
+
+    match ruby::ruby_array_to_slice(v) {
+        // Positions are `[line, column]` pairs
+        [line, column] if is_fixnum(*line) && is_fixnum(*column) => {
+            let line = ruby::rubyfmt_rb_num2ll(*line);
+            if line > 0 {
+                Some(line as LineNumber)
+            } else {
+                None
+            }
+        }
+        elements => elements.iter().filter_map(|e| first_line_in(*e)).min(),
+    }
+}
+
+unsafe fn is_array(v: VALUE) -> bool {
+    matches!(ruby::rubyfmt_rb_type(v), ruby_value_type::RUBY_T_ARRAY)
+}
+
+unsafe fn is_fixnum(v: VALUE) -> bool {
+    matches!(ruby::rubyfmt_rb_type(v), ruby_value_type::RUBY_T_FIXNUM)
+}
@@ -0,0 +1,58 @@
+//! A hook for running custom text passes over the formatted output, for
+//! cleanups that rubyfmt doesn't model itself.
+
+use log::warn;
+
+use crate::config::Config;
+use crate::{format_buffer_with_config, RichFormatError};
+
+/// Rewrites the output of rubyfmt after it's been rendered. Any
+/// `Fn(String) -> String` is a post-processor.
+pub trait PostProcessor {
+    fn process(&self, formatted: String) -> String;
+}
+
+impl<F: Fn(String) -> String> PostProcessor for F {
+    fn process(&self, formatted: String) -> String {
+        self(formatted)
+    }
+}
+
+/// Formats `buf` with `config`, then runs `post_processor` over the result:
+///
+/// ```
+/// use rubyfmt::Config;
+///
+/// assert_eq!(rubyfmt::rubyfmt_init(), 0);
+///
+/// let rename = |s: String| s.replace("OldName", "NewName");
+/// let output = rubyfmt::format_with_post_processor("puts('OldName')\n", &Config::default(), &rename);
+///
+/// assert_eq!(output.unwrap(), "puts(\"NewName\")\n");
+/// ```
+///
+/// A post-processor whose output rubyfmt would change, or that changes its
+/// own output when it's run again, means files never stay formatted. This
+/// formats the output a second time to check for that, and logs a warning
+/// if it happens.
+pub fn format_with_post_processor(
+    buf: &str,
+    config: &Config,
+    post_processor: &dyn PostProcessor,
+) -> Result<String, RichFormatError> {
+    let processed = post_processor.process(format_buffer_with_config(buf, config)?);
+
+    match format_buffer_with_config(&processed, config) {
+        Ok(reformatted) => {
+            if post_processor.process(reformatted) != processed {
+                warn!("formatting the post-processed output again changes it, so it won't stay formatted");
+            }
+        }
+        Err(e) => warn!(
+            "the post-processed output couldn't be formatted again: {:?}",
+            e
+        ),
+    }
+
+    Ok(processed)
+}
@@ -0,0 +1,46 @@
+use std::ops::AddAssign;
+use std::time::{Duration, Instant};
+
+/// Time spent in each phase of formatting one or more files.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Profile {
+    /// Running ripper and deserializing its output.
+    pub parse: Duration,
+    /// Walking the parse tree to build the render queue.
+    pub format: Duration,
+    /// Building the `Intermediary` tokens from the render queue.
+    pub intermediary: Duration,
+    /// Writing the final tokens out.
+    pub render: Duration,
+}
+
+impl Profile {
+    pub fn phases(&self) -> [(&'static str, Duration); 4] {
+        [
+            ("parse", self.parse),
+            ("format", self.format),
+            ("intermediary", self.intermediary),
+            ("render", self.render),
+        ]
+    }
+
+    pub fn total(&self) -> Duration {
+        self.parse + self.format + self.intermediary + self.render
+    }
+}
+
+impl AddAssign for Profile {
+    fn add_assign(&mut self, other: Profile) {
+        self.parse += other.parse;
+        self.format += other.format;
+        self.intermediary += other.intermediary;
+        self.render += other.render;
+    }
+}
+
+pub fn timed<T>(phase: &mut Duration, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let res = f();
+    *phase += start.elapsed();
+    res
+}
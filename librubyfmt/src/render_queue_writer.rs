@@ -1,30 +1,58 @@
+use crate::config::Config;
+use crate::highlight::TokenClass;
 use crate::intermediary::{BlanklineReason, Intermediary};
 use crate::line_tokens::*;
 use crate::parser_state::FormattingContext;
+use crate::profile::{timed, Profile};
 use crate::render_targets::{AbstractTokenTarget, BreakableEntry, ConvertType};
+use crate::transforms::{RenderedTransform, TransformKind};
+use crate::types::LineNumber;
 #[cfg(debug_assertions)]
 use log::debug;
 use std::io::{self, Write};
 
 pub const MAX_LINE_LENGTH: usize = 120;
 
+/// Where the final tokens are written to. Anything that's `Write` gets
+/// just the Ruby, highlighters also get what kind of token it was.
+pub trait TokenSink {
+    fn write_token(&mut self, class: TokenClass, ruby: String) -> io::Result<()>;
+}
+
+impl<W: Write> TokenSink for W {
+    fn write_token(&mut self, _: TokenClass, ruby: String) -> io::Result<()> {
+        self.write_all(ruby.as_bytes())
+    }
+}
+
 pub struct RenderQueueWriter {
     tokens: Vec<ConcreteLineTokenAndTargets>,
+    config: Config,
 }
 
 impl RenderQueueWriter {
-    pub fn new(tokens: Vec<ConcreteLineTokenAndTargets>) -> Self {
-        RenderQueueWriter { tokens }
+    pub fn new(tokens: Vec<ConcreteLineTokenAndTargets>, config: Config) -> Self {
+        RenderQueueWriter { tokens, config }
     }
 
-    pub fn write<W: Write>(self, writer: &mut W) -> io::Result<()> {
-        let mut accum = Intermediary::new();
+    pub fn write<S: TokenSink>(
+        self,
+        sink: &mut S,
+        profile: &mut Profile,
+    ) -> io::Result<Vec<RenderedTransform>> {
+        let mut accum = Intermediary::new(self.config);
         #[cfg(debug_assertions)]
         {
             debug!("first tokens {:?}", self.tokens);
         }
-        Self::render_as(&mut accum, self.tokens);
-        Self::write_final_tokens(writer, accum.into_tokens())
+        let tokens = self.tokens;
+        timed(&mut profile.intermediary, || {
+            Self::render_as(&mut accum, tokens)
+        });
+        let (tokens, transforms) = accum.into_tokens();
+        timed(&mut profile.render, || {
+            Self::write_final_tokens(sink, tokens, transforms)
+        })
     }
 
     fn render_as(accum: &mut Intermediary, tokens: Vec<ConcreteLineTokenAndTargets>) {
@@ -102,31 +130,52 @@ impl RenderQueueWriter {
     }
 
     fn format_breakable_entry(accum: &mut Intermediary, be: BreakableEntry) {
-        let length = be.single_line_string_length();
-
-        if (length > MAX_LINE_LENGTH || be.is_multiline())
-            && be.entry_formatting_context() != FormattingContext::StringEmbexpr
-        {
-            Self::render_as(accum, be.into_tokens(ConvertType::MultiLine));
-        } else {
-            Self::render_as(accum, be.into_tokens(ConvertType::SingleLine));
-            // after running accum looks like this (or some variant):
-            // [.., Comma, Space, DirectPart {part: ""}, <close_delimiter>]
-            // so we remove items at positions length-2 until there is nothing
-            // in that position that is garbage.
-            accum.clear_breakable_garbage();
+        match Self::breakable_layout(&be) {
+            ConvertType::MultiLine => {
+                Self::render_as(accum, be.into_tokens(ConvertType::MultiLine))
+            }
+            ConvertType::SingleLine => {
+                Self::render_as(accum, be.into_tokens(ConvertType::SingleLine));
+                // after running accum looks like this (or some variant):
+                // [.., Comma, Space, DirectPart {part: ""}, <close_delimiter>]
+                // so we remove items at positions length-2 until there is nothing
+                // in that position that is garbage.
+                accum.clear_breakable_garbage();
+            }
         }
     }
 
-    fn write_final_tokens<W: Write>(
-        writer: &mut W,
-        mut tokens: Vec<ConcreteLineToken>,
-    ) -> io::Result<()> {
-        #[cfg(debug_assertions)]
-        {
-            debug!("final tokens: {:?}", tokens);
+    // Picks one of the two layouts of a breakable. The rules are checked in
+    // order, and the first one that applies decides:
+    //
+    // 1. Inside string interpolation it's always on one line.
+    // 2. If it already spanned multiple lines in the source it stays broken,
+    //    so that formatting the output again makes the same choice.
+    // 3. It's on one line if the single line form of its contents is at
+    //    most MAX_LINE_LENGTH long. That's only the contents: the column it
+    //    starts at and whatever comes before it on the line aren't counted,
+    //    so this isn't the same as the whole line fitting.
+    // 4. Otherwise it breaks.
+    //
+    // These only spell out the choice rubyfmt already made. A breakable only ever has these two layouts, and each rule
+    // depends only on the breakable itself, so there's never a tie. Nested
+    // breakables are laid out outermost first, since an inner one is only
+    // rendered once the tokens of the outer one are, which means that when
+    // both are too long the leftmost break is always taken first. The inner
+    // one is then judged on its own contents.
+    fn breakable_layout(be: &BreakableEntry) -> ConvertType {
+        if be.entry_formatting_context() == FormattingContext::StringEmbexpr {
+            ConvertType::SingleLine
+        } else if be.is_multiline() {
+            ConvertType::MultiLine
+        } else if be.single_line_string_length() <= MAX_LINE_LENGTH {
+            ConvertType::SingleLine
+        } else {
+            ConvertType::MultiLine
         }
+    }
 
+    fn trim_trailing_blank_lines(tokens: &mut Vec<ConcreteLineToken>) {
         loop {
             let len = tokens.len();
             if len < 2 {
@@ -150,11 +199,58 @@ impl RenderQueueWriter {
                 break;
             }
         }
+    }
 
-        for line_token in tokens.into_iter() {
+    fn write_final_tokens<S: TokenSink>(
+        sink: &mut S,
+        mut tokens: Vec<ConcreteLineToken>,
+        mut transforms: Vec<(usize, TransformKind)>,
+    ) -> io::Result<Vec<RenderedTransform>> {
+        #[cfg(debug_assertions)]
+        {
+            debug!("final tokens: {:?}", tokens);
+        }
+
+        // The code before a `__END__` ends with one newline just like a
+        // file without one, the data after it is left exactly as it was
+        let data = match tokens.iter().position(|t| t == &ConcreteLineToken::DataEnd) {
+            Some(idx) => tokens.split_off(idx),
+            None => Vec::new(),
+        };
+        Self::trim_trailing_blank_lines(&mut tokens);
+        tokens.extend(data);
+
+        // Later fixups can leave an inserted newline on its own, in which
+        // case it didn't end up making a blank line
+        transforms.retain(|(idx, kind)| {
+            !kind.inserts_blank_line()
+                || matches!(
+                    (tokens.get(*idx), tokens.get(*idx + 1)),
+                    (
+                        Some(ConcreteLineToken::HardNewLine),
+                        Some(ConcreteLineToken::HardNewLine)
+                    )
+                )
+        });
+        transforms.sort_by_key(|(idx, _)| *idx);
+
+        let mut applied = Vec::with_capacity(transforms.len());
+        let mut transforms = transforms.into_iter().peekable();
+        let mut newlines_written = 0;
+        for (idx, line_token) in tokens.into_iter().enumerate() {
+            let class = TokenClass::of(&line_token);
             let s = line_token.into_ruby();
-            write!(writer, "{}", s)?
+            newlines_written += s.matches('\n').count() as LineNumber;
+            while let Some((_, kind)) = transforms.next_if(|(t_idx, _)| *t_idx == idx) {
+                // The code below an inserted blank line is one more line down
+                let blank_lines = kind.inserts_blank_line() as LineNumber;
+                applied.push(RenderedTransform {
+                    kind,
+                    output_line: newlines_written + 1 + blank_lines,
+                });
+            }
+            sink.write_token(class, s)?
         }
-        Ok(())
+        Ok(applied)
     }
 }
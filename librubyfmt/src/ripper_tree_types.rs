@@ -502,10 +502,23 @@ pub struct HeredocStringLiteral(
 def_tag!(string_literal_tag, "string_literal");
 #[derive(RipperDeserialize, Debug, Clone)]
 pub enum StringLiteral {
+    // Only produced when string normalization is turned off. This has to be
+    // tried before `Normal`, which is the same apart from the delimiters.
+    Preserved(
+        string_literal_tag,
+        StringContent,
+        StartEnd,
+        StringDelimiters,
+    ),
     Normal(string_literal_tag, StringContent, StartEnd),
     Heredoc(string_literal_tag, HeredocStringLiteral, StringContent),
 }
 
+/// The opening and closing delimiters of a string, i.e. `'` and `'`, or
+/// `%q(` and `)`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct StringDelimiters(pub String, pub String);
+
 def_tag!(xstring_literal_tag, "xstring_literal");
 #[derive(Deserialize, Debug, Clone)]
 pub struct XStringLiteral(pub xstring_literal_tag, pub Vec<StringContentPart>);
@@ -516,19 +529,23 @@ pub struct DynaSymbol(
     pub dyna_symbol_tag,
     pub StringContentOrStringContentParts,
     StartEnd,
+    #[serde(default)]
+    /// Only set when string normalization is turned off, like the
+    /// delimiters of `StringLiteral::Preserved`
+    pub Option<StringDelimiters>,
 );
 
 impl DynaSymbol {
     pub fn to_string_literal(self) -> StringLiteral {
-        match self.1 {
-            StringContentOrStringContentParts::StringContent(sc) => {
-                StringLiteral::Normal(string_literal_tag, sc, self.2)
+        let sc = match self.1 {
+            StringContentOrStringContentParts::StringContent(sc) => sc,
+            StringContentOrStringContentParts::StringContentParts(scp) => {
+                StringContent(string_content_tag, scp)
             }
-            StringContentOrStringContentParts::StringContentParts(scp) => StringLiteral::Normal(
-                string_literal_tag,
-                StringContent(string_content_tag, scp),
-                self.2,
-            ),
+        };
+        match self.3 {
+            Some(delims) => StringLiteral::Preserved(string_literal_tag, sc, self.2, delims),
+            None => StringLiteral::Normal(string_literal_tag, sc, self.2),
         }
     }
 }
@@ -935,8 +952,9 @@ impl Params {
 //      false],
 //     [[:void_stmt]]]]]]
 // this difference is in the "rest_args" position, and on 2.5 is a literal
-// integer 0 and on 2.6 a unit parser tag [:excessed_comma]. These nodes don't
-// appear to cause any semantic difference in the program.
+// integer 0 and on 2.6 a unit parser tag [:excessed_comma]. Both stand for the
+// trailing comma in `|x,|`, which makes the block destructure its argument and
+// so has to be kept.
 // So:
 //   the Zero deserialzer deals with the 2.5 case, and the ExcessedComma node
 //   deals with the 2.6 case, I will note that I tried to collapse them in to
@@ -963,7 +981,13 @@ impl Params {
         vec![
             (self.1).is_some(),
             (self.2).is_some(),
-            (self.3).is_some(),
+            // A trailing comma isn't a parameter of its own, it's written
+            // straight after the ones before it
+            matches!(
+                self.3,
+                Some(RestParamOr0OrExcessedCommaOrArgsForward::RestParam(_))
+                    | Some(RestParamOr0OrExcessedCommaOrArgsForward::ArgsForward(_))
+            ),
             (self.4).is_some(),
             (self.5).is_some(),
             (self.6).is_some(),
@@ -1155,7 +1179,15 @@ pub struct BareAssocHash(pub bare_assoc_hash_tag, pub Vec<AssocNewOrAssocSplat>)
 
 def_tag!(hash_tag, "hash");
 #[derive(Deserialize, Debug, Clone)]
-pub struct Hash(pub hash_tag, pub Option<AssocListFromArgs>, pub StartEnd);
+pub struct Hash(
+    pub hash_tag,
+    pub Option<AssocListFromArgs>,
+    pub StartEnd,
+    #[serde(default)]
+    /// Whether there's a `# rubyfmt:sort-keys` comment above the hash or
+    /// after its opening brace
+    pub bool,
+);
 
 def_tag!(assoclist_from_args_tag, "assoclist_from_args");
 #[derive(Deserialize, Debug, Clone)]
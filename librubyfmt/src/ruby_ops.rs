@@ -1,5 +1,7 @@
 use crate::file_comments::FileComments;
 use crate::ruby::*;
+use crate::types::{ColNumber, LineNumber};
+use crate::SyntaxErrorLocation;
 
 pub fn setup_ruby() -> Result<(), ()> {
     unsafe {
@@ -24,7 +26,9 @@ pub struct Parser(VALUE);
 
 #[derive(Debug, Clone)]
 pub enum ParseError {
-    SyntaxError,
+    SyntaxError(Option<SyntaxErrorLocation>),
+    // Ruby stopped reading the input early, at this line
+    IncompleteParse(LineNumber),
     OtherRubyError(String),
 }
 
@@ -33,6 +37,22 @@ impl Parser {
         rb_funcall(parser_instance, intern!("parse"), 0)
     }
 
+    unsafe extern "C" fn real_preserve_strings(parser_instance: VALUE) -> VALUE {
+        rb_funcall(parser_instance, intern!("preserve_strings!"), 0)
+    }
+
+    unsafe extern "C" fn real_keep_comment_indents(parser_instance: VALUE) -> VALUE {
+        rb_funcall(parser_instance, intern!("keep_comment_indents!"), 0)
+    }
+
+    unsafe extern "C" fn real_unlexed_line(parser_instance: VALUE) -> VALUE {
+        rb_funcall(parser_instance, intern!("unlexed_line"), 0)
+    }
+
+    unsafe extern "C" fn real_syntax_error(parser_instance: VALUE) -> VALUE {
+        rb_funcall(parser_instance, intern!("syntax_error"), 0)
+    }
+
     pub fn new(buf: &str) -> Self {
         unsafe {
             let buffer_string = rb_utf8_str_new(buf.as_ptr() as _, buf.len() as libc::c_long);
@@ -42,37 +62,82 @@ impl Parser {
         }
     }
 
+    /// Keeps string literals as they were written, see
+    /// `Config::normalize_strings`.
+    pub fn preserve_strings(self) -> Result<Self, ParseError> {
+        self.call(Parser::real_preserve_strings)?;
+        Ok(self)
+    }
+
+    /// Keeps the layout of every comment block, see
+    /// `Config::comment_indent`.
+    pub fn keep_comment_indents(self) -> Result<Self, ParseError> {
+        self.call(Parser::real_keep_comment_indents)?;
+        Ok(self)
+    }
+
     pub fn parse(self) -> Result<(RipperTree, FileComments, Option<&'static str>), ParseError> {
-        let mut state = 0;
-        let maybe_ret_tuple =
-            unsafe { rb_protect(Parser::real_run_parser as _, self.0 as _, &mut state) };
-        if state == 0 {
-            if maybe_ret_tuple != Qnil {
-                let ret_tuple = unsafe { ruby_array_to_slice(maybe_ret_tuple) };
-                if let [tree, comments, lines, last_lineno, end_contents] = ret_tuple {
-                    let fc = FileComments::from_ruby_hash(*comments, *lines, *last_lineno);
-                    let end_contents = unsafe {
-                        if rubyfmt_rb_nil_p(*end_contents) != 0 {
-                            None
-                        } else {
-                            Some(ruby_string_to_str(*end_contents))
-                        }
-                    };
-                    Ok((RipperTree::new(*tree), fc, end_contents))
-                } else {
-                    panic!(
-                        "expected return tuple to match expected, actually got: {}",
-                        ret_tuple.len(),
-                    )
+        let maybe_ret_tuple = self.call(Parser::real_run_parser)?;
+        if maybe_ret_tuple != Qnil {
+            let ret_tuple = unsafe { ruby_array_to_slice(maybe_ret_tuple) };
+            if let [tree, comments, lines, last_lineno, end_contents] = ret_tuple {
+                let unlexed_line = self.call(Parser::real_unlexed_line)?;
+                if unlexed_line != Qnil {
+                    let line = unsafe { rubyfmt_rb_num2ll(unlexed_line) };
+                    return Err(ParseError::IncompleteParse(line as LineNumber));
                 }
+                let fc = FileComments::from_ruby_hash(*comments, *lines, *last_lineno);
+                let end_contents = unsafe {
+                    if rubyfmt_rb_nil_p(*end_contents) != 0 {
+                        None
+                    } else {
+                        Some(ruby_string_to_str(*end_contents))
+                    }
+                };
+                Ok((RipperTree::new(*tree), fc, end_contents))
             } else {
-                Err(ParseError::SyntaxError)
+                panic!(
+                    "expected return tuple to match expected, actually got: {}",
+                    ret_tuple.len(),
+                )
             }
+        } else {
+            Err(ParseError::SyntaxError(self.syntax_error_location()?))
+        }
+    }
+}
+
+impl Parser {
+    /// Calls `f` with the parser instance, turning anything it raises into
+    /// an error instead of letting it unwind through Rust.
+    fn call(&self, f: unsafe extern "C" fn(VALUE) -> VALUE) -> Result<VALUE, ParseError> {
+        let mut state = 0;
+        let ret = unsafe { rb_protect(f as _, self.0, &mut state) };
+        if state == 0 {
+            Ok(ret)
         } else {
             let s = current_exception_as_rust_string();
             Err(ParseError::OtherRubyError(s))
         }
     }
+
+    fn syntax_error_location(&self) -> Result<Option<SyntaxErrorLocation>, ParseError> {
+        let error = self.call(Parser::real_syntax_error)?;
+        if error == Qnil {
+            return Ok(None);
+        }
+        let location = unsafe {
+            match ruby_array_to_slice(error) {
+                [line, column, message] => Some(SyntaxErrorLocation {
+                    line: rubyfmt_rb_num2ll(*line) as LineNumber,
+                    column: rubyfmt_rb_num2ll(*column) as ColNumber,
+                    message: ruby_string_to_str(*message).to_string(),
+                }),
+                _ => None,
+            }
+        };
+        Ok(location)
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -0,0 +1,128 @@
+//! The discretionary changes rubyfmt makes while formatting, for tools that
+//! explain a diff to their users.
+
+use similar::{capture_diff_slices, Algorithm, DiffOp};
+
+use crate::types::LineNumber;
+
+/// A change made to the layout of the file, beyond fixing indentation and
+/// spacing.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TransformKind {
+    /// A blank line was inserted after the `end` of a block, method or class.
+    BlankLineAfterEnd,
+    /// A blank line was inserted before a `class` or `module`.
+    BlankLineBeforeClassOrModule,
+    /// A blank line was inserted after a group of `require`s.
+    BlankLineAfterRequires,
+    /// One of the extra blank lines between two methods was inserted, see
+    /// `Config::blank_lines_between_defs`.
+    BlankLineBetweenDefs,
+    /// A blank line was inserted before the mixins at the top of a `class`
+    /// or `module`, see `MixinSpacing::Always`.
+    BlankLineBeforeMixin,
+    /// The blank line before the mixins at the top of a `class` or `module`
+    /// was removed, see `MixinSpacing::Never`.
+    RemovedBlankLineBeforeMixin,
+    /// The entries of a hash were sorted by key, see
+    /// `Config::sort_hash_keys`.
+    SortedHashKeys,
+    /// A `rescue` modifier was rewritten as a `begin` block, see
+    /// `InlineRescue::Expand`.
+    ExpandedInlineRescue,
+    /// The parens around the condition of an `if`, `unless`, `elsif`,
+    /// `while` or `until` were removed, see `ConditionParens::Strip`.
+    StrippedConditionParens,
+    /// The hashes in an array were lined up like a table, see
+    /// `Config::align_hash_rows`.
+    AlignedHashRows,
+}
+
+impl TransformKind {
+    pub(crate) fn inserts_blank_line(self) -> bool {
+        matches!(
+            self,
+            TransformKind::BlankLineAfterEnd
+                | TransformKind::BlankLineBeforeClassOrModule
+                | TransformKind::BlankLineAfterRequires
+                | TransformKind::BlankLineBetweenDefs
+                | TransformKind::BlankLineBeforeMixin
+        )
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AppliedTransform {
+    pub kind: TransformKind,
+    /// The line of the source this happened at. For blank lines, that's the
+    /// line of the code that a blank line was inserted above or removed
+    /// from above. A sorted hash or aligned array is at the line it starts
+    /// on, and an expanded `rescue` modifier at the line of the `rescue`.
+    pub line: LineNumber,
+}
+
+/// A blank line the renderer inserted or removed, at the line of the output
+/// with the code below it. Only the formatting pass knows which source line
+/// it's on, so these get matched up with the source afterwards.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct RenderedTransform {
+    pub kind: TransformKind,
+    pub output_line: LineNumber,
+}
+
+/// The transforms applied while formatting a file.
+#[derive(Debug, Default)]
+pub(crate) struct FoundTransforms {
+    pub applied: Vec<AppliedTransform>,
+    pub rendered: Vec<RenderedTransform>,
+}
+
+impl FoundTransforms {
+    /// All of the transforms at their lines of `source`, which was formatted
+    /// into `output`, in the order they're in the source.
+    ///
+    /// The lines of the output are matched to the source by diffing them
+    /// with their indentation ignored. A line that formatting changed
+    /// doesn't match anything, so it's taken to be the first nonblank line
+    /// after the source line of the closest line above it that does.
+    pub fn into_applied(self, source: &str, output: &str) -> Vec<AppliedTransform> {
+        let source_lines: Vec<&str> = source.lines().map(str::trim).collect();
+        let output_lines: Vec<&str> = output.lines().map(str::trim).collect();
+        let mut matches = vec![None; output_lines.len()];
+        for op in capture_diff_slices(Algorithm::Myers, &source_lines, &output_lines) {
+            if let DiffOp::Equal {
+                old_index,
+                new_index,
+                len,
+            } = op
+            {
+                for offset in 0..len {
+                    matches[new_index + offset] = Some(old_index + offset);
+                }
+            }
+        }
+
+        let source_line = |output_line: LineNumber| {
+            let idx = output_line as usize - 1;
+            if let Some(Some(source_idx)) = matches.get(idx) {
+                return *source_idx as LineNumber + 1;
+            }
+            let after = matches[..idx.min(matches.len())]
+                .iter()
+                .rev()
+                .find_map(|m| *m)
+                .map(|source_idx| source_idx + 1)
+                .unwrap_or(0);
+            let nonblank = (after..source_lines.len()).find(|l| !source_lines[*l].is_empty());
+            nonblank.unwrap_or(after) as LineNumber + 1
+        };
+
+        let mut applied = self.applied;
+        applied.extend(self.rendered.into_iter().map(|t| AppliedTransform {
+            kind: t.kind,
+            line: source_line(t.output_line),
+        }));
+        applied.sort_by_key(|t| t.line);
+        applied
+    }
+}
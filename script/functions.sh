@@ -36,8 +36,10 @@ diff_files() {
 }
 
 
+# Any arguments after the folder are passed on to rubyfmt
 test_fixtures_folder() {
     current_dir="$1"
+    shift
 
     # Fallback to * (all tests)
     fixture_name=${FIXTURE_NAME:-*}
@@ -46,11 +48,11 @@ test_fixtures_folder() {
       actual_file="${expected_file//expected/actual}"
 
       ## Test if the formatting works as expected
-      f_rubyfmt < "$actual_file" > /tmp/out.rb
+      f_rubyfmt "$@" < "$actual_file" > /tmp/out.rb
       diff_files o /tmp/out.rb "$expected_file"
 
       ## Test if the formatting is idempotent
-      f_rubyfmt < "$expected_file" > /tmp/out.rb
+      f_rubyfmt "$@" < "$expected_file" > /tmp/out.rb
       diff_files i /tmp/out.rb "$expected_file"
     done
 
@@ -62,7 +64,7 @@ test_fixtures_folder() {
         fixture_version=${base#"ruby-"}
         if [[ $(echo "$fixture_version<=$RUBY_VERSION" | bc -l) -ne 0 ]]
         then
-            test_fixtures_folder "$dir"
+            test_fixtures_folder "$dir" "$@"
         fi
     done
 }
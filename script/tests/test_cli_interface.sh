@@ -636,6 +636,389 @@ DIFF
     )
 }
 
+test_jobs_flag_output_is_deterministic() {
+    (
+    cd "$(mktemp -d)"
+
+    mkdir -p tree/a/b tree/c
+    echo "a 1,2,3" > tree/z.rb
+    echo "a 4,5,6" > tree/a/y.rb
+    echo "a 7,8,9" > tree/a/b/x.rb
+    echo "a 1,2" > tree/c/w.rb
+    echo "a 3" > tree/c/v.rb
+
+    f_rubyfmt -j1 -- tree/ > sequential.rb
+    f_rubyfmt -j4 -- tree/ > parallel.rb
+    diff_files o sequential.rb parallel.rb
+
+    set +e
+    f_rubyfmt -j1 --check -- tree/ > sequential.diff
+    f_rubyfmt -j4 --check -- tree/ > parallel.diff
+    set -e
+    diff_files o sequential.diff parallel.diff
+
+    # Errors come in the same order, so --fail-fast stops at the same file
+    echo "def foo(" > tree/a/broken.rb
+    echo "class" > tree/c/broken.rb
+    set +e
+    f_rubyfmt -j1 -- tree/ > sequential_errors.rb 2> sequential_errors.txt
+    f_rubyfmt -j4 -- tree/ > parallel_errors.rb 2> parallel_errors.txt
+    f_rubyfmt -j1 --fail-fast -- tree/ > /dev/null 2> sequential_fail_fast.txt
+    f_rubyfmt -j4 --fail-fast -- tree/ > /dev/null 2> parallel_fail_fast.txt
+    set -e
+    diff_files o sequential_errors.rb parallel_errors.rb
+    diff_files o sequential_errors.txt parallel_errors.txt
+    diff_files o sequential_fail_fast.txt parallel_fail_fast.txt
+    )
+}
+
+test_verbose_flag() {
+    (
+    cd "$(mktemp -d)"
+
+    printf 'a 1\nclass Foo\nend\n' > changed.rb
+    echo "a(1, 2, 3)" > unchanged.rb
+
+    f_rubyfmt --verbose -- changed.rb unchanged.rb > out.rb 2> log.txt
+    cat log.txt
+
+    grep -q "changed.rb: formatted" log.txt
+    grep -q "unchanged.rb: already formatted" log.txt
+    grep -q "inserting blank line: ClassOrModule" log.txt
+
+    # A blank line after the very first line of a file is logged too
+    printf 'a\nclass Foo\nend\n' > short_first_line.rb
+    f_rubyfmt --verbose -- short_first_line.rb > /dev/null 2> log.txt
+    grep -q "inserting blank line: ClassOrModule" log.txt
+
+    f_rubyfmt -- changed.rb unchanged.rb > quiet.rb 2> quiet_log.txt
+    diff_files o quiet.rb out.rb
+    if [ -s quiet_log.txt ];
+    then
+        echo "rubyfmt logged without --verbose"
+        exit 1
+    fi
+    )
+}
+
+test_log_level() {
+    (
+    cd "$(mktemp -d)"
+
+    printf 'a 1\nclass Foo\nend\n' > changed.rb
+
+    f_rubyfmt --log-level=debug -- changed.rb > /dev/null 2> log.txt
+    grep -q "inserting blank line: ClassOrModule" log.txt
+    grep -q "on_line called" log.txt
+
+    f_rubyfmt --log-level=error --verbose -- changed.rb > /dev/null 2> log.txt
+    grep -q "inserting blank line: ClassOrModule" log.txt
+
+    f_rubyfmt --log-level=warn -- changed.rb > /dev/null 2> log.txt
+    if [ -s log.txt ];
+    then
+        echo "rubyfmt logged below the log level"
+        exit 1
+    fi
+
+    # Release builds only log warnings and errors by default
+    f_rubyfmt -- changed.rb > /dev/null 2> log.txt
+    if [ -s log.txt ];
+    then
+        echo "rubyfmt logged below the default log level"
+        exit 1
+    fi
+    )
+}
+
+test_glob_patterns() {
+    (
+    cd "$(mktemp -d)"
+
+    mkdir -p lib/a/b
+    echo "a 1,2,3" > top.rb
+    echo "a 4,5,6" > lib/one.rb
+    echo "a 7,8,9" > lib/a/two.rb
+    echo "a 1,2" > lib/a/b/three.rb
+    echo "a 1,2" > lib/a/b/not_ruby.txt
+
+    # single level globs only match direct children
+    f_rubyfmt -i -- "lib/*.rb"
+    echo "a(4, 5, 6)" > expected_one.rb
+    echo "a 7,8,9" > expected_two.rb
+    diff_files o lib/one.rb expected_one.rb
+    diff_files o lib/a/two.rb expected_two.rb
+
+    # recursive globs match every level
+    f_rubyfmt -i -- "lib/**/*.rb"
+    echo "a(7, 8, 9)" > expected_two.rb
+    echo "a(1, 2)" > expected_three.rb
+    echo "a 1,2" > expected_not_ruby.txt
+    diff_files o lib/a/two.rb expected_two.rb
+    diff_files o lib/a/b/three.rb expected_three.rb
+    diff_files o lib/a/b/not_ruby.txt expected_not_ruby.txt
+
+    # literal paths are used as is
+    f_rubyfmt -i -- top.rb
+    echo "a(1, 2, 3)" > expected_top.rb
+    diff_files o top.rb expected_top.rb
+    )
+}
+
+test_profile_flag() {
+    (
+    cd "$(mktemp -d)"
+
+    echo "a 1,2,3" > input.rb
+    echo "a(1, 2, 3)" > expected.rb
+
+    f_rubyfmt --profile -- input.rb > out.rb 2> profile.txt
+    cat profile.txt
+
+    diff_files o out.rb expected.rb
+    for phase in phase parse format intermediary render total
+    do
+        grep -q "^$phase " profile.txt
+    done
+    )
+}
+
+test_json_report() {
+    (
+    cd "$(mktemp -d)"
+
+    mkdir -p tree/nested
+    echo "a 1,2,3" > tree/changed.rb
+    echo "a(1, 2, 3)" > tree/unchanged.rb
+    echo "def foo(" > tree/nested/broken.rb
+
+    f_rubyfmt --report=json -- tree > report.json 2> /dev/null
+    cat report.json
+
+    ruby -rjson -e '
+      report = JSON.parse(File.read("report.json"))
+      raise "not an array" unless report.is_a?(Array)
+      report.each do |entry|
+        raise "bad path: #{entry}" unless entry["path"].is_a?(String)
+        raise "bad changed: #{entry}" unless [true, false].include?(entry["changed"])
+        raise "bad error: #{entry}" unless entry["error"].nil? || entry["error"].is_a?(String)
+        raise "bad diff: #{entry}" if entry.key?("diff") && !entry["diff"].is_a?(String)
+        raise "unknown keys: #{entry}" unless (entry.keys - %w[path changed error diff]).empty?
+      end
+
+      by_path = report.to_h { |entry| [entry["path"], entry] }
+      raise "wrong files: #{by_path.keys}" unless by_path.keys == %w[tree/changed.rb tree/nested/broken.rb tree/unchanged.rb]
+      raise "changed.rb" unless by_path["tree/changed.rb"]["changed"] && by_path["tree/changed.rb"]["diff"].include?("+a(1, 2, 3)")
+      raise "unchanged.rb" if by_path["tree/unchanged.rb"]["changed"] || by_path["tree/unchanged.rb"].key?("diff")
+      raise "broken.rb" unless by_path["tree/nested/broken.rb"]["error"] == "syntax error"
+    '
+
+    # The report is emitted once, in the same order every time
+    f_rubyfmt --report=json -- tree > report_again.json 2> /dev/null
+    diff_files o report_again.json report.json
+
+    # Errors that stop rubyfmt early still get reported
+    if echo "def foo(" | f_rubyfmt --report=json > stdin_report.json 2> /dev/null; then
+        echo "formatting broken code from STDIN should fail"
+        exit 1
+    fi
+    if f_rubyfmt --report=json --fail-fast -- tree/nested > fail_fast_report.json 2> /dev/null; then
+        echo "--fail-fast with a broken file should fail"
+        exit 1
+    fi
+    ruby -rjson -e '
+      stdin = JSON.parse(File.read("stdin_report.json"))
+      raise "stdin: #{stdin}" unless stdin == [{"path" => "stdin", "changed" => false, "error" => "syntax error"}]
+      fail_fast = JSON.parse(File.read("fail_fast_report.json"))
+      raise "fail fast: #{fail_fast}" unless fail_fast.map { |entry| entry["path"] } == %w[tree/nested/broken.rb]
+    '
+    )
+}
+
+test_fragment_kinds() {
+    (
+    cd "$(mktemp -d)"
+
+    printf 'a 1,2,3\nb 4\n' | f_rubyfmt --fragment-kind file > out.rb
+    printf 'a(1, 2, 3)\nb(4)\n' > expected.rb
+    diff_files o out.rb expected.rb
+
+    echo "foo   bar,baz" | f_rubyfmt --fragment-kind expression > out.rb
+    echo "foo(bar, baz)" > expected.rb
+    diff_files o out.rb expected.rb
+
+    printf 'return if x\nfoo 1\nrescue StandardError => e\nhandle e\n' | f_rubyfmt --fragment-kind method-body > out.rb
+    printf 'return if x\nfoo(1)\nrescue StandardError => e\nhandle(e)\n' > expected.rb
+    diff_files o out.rb expected.rb
+
+    # Heredoc bodies and multiline strings are left as they are
+    cat > body.rb <<'RUBY'
+text = <<EOS
+  two spaces
+    four spaces
+EOS
+puts "a
+  b"
+RUBY
+    f_rubyfmt --fragment-kind method-body < body.rb > out.rb
+    cat > expected.rb <<'RUBY'
+text = <<EOS
+  two spaces
+    four spaces
+EOS
+puts("a
+  b")
+RUBY
+    diff_files o out.rb expected.rb
+
+    printf 'foo 1' | f_rubyfmt --fragment-kind method-body --no-final-newline-normalization > out.rb
+    printf 'foo(1)' > expected.rb
+    diff_files o out.rb expected.rb
+
+    # Valid ruby that isn't the declared kind is a clear error
+    if printf 'a\nb\n' | f_rubyfmt --fragment-kind expression > out.rb 2> err.txt
+    then
+        echo "two statements formatted as an expression"
+        exit 1
+    fi
+    grep -q "expected a single expression" err.txt
+
+    if echo "class Foo; end" | f_rubyfmt --fragment-kind method-body > out.rb 2> err.txt
+    then
+        echo "a class formatted as a method body"
+        exit 1
+    fi
+    grep -q "isn't allowed inside a method body" err.txt
+    )
+}
+
+test_no_final_newline_normalization() {
+    (
+    cd "$(mktemp -d)"
+
+    printf 'a 1,2,3' | f_rubyfmt --no-final-newline-normalization > out.rb
+    printf 'a(1, 2, 3)' > expected.rb
+    diff_files o out.rb expected.rb
+
+    printf 'a 1,2,3\n\n\n' | f_rubyfmt --no-final-newline-normalization > out.rb
+    printf 'a(1, 2, 3)\n\n\n' > expected.rb
+    diff_files o out.rb expected.rb
+
+    # With a `__END__`, the code keeps its newlines and the data is untouched
+    printf 'a 1,2,3\n\n\n__END__\ndata\n\n' | f_rubyfmt --no-final-newline-normalization > out.rb
+    printf 'a(1, 2, 3)\n\n\n__END__\ndata\n\n' > expected.rb
+    diff_files o out.rb expected.rb
+
+    # Without the flag there's always exactly one
+    printf 'a 1,2,3' | f_rubyfmt > out.rb
+    printf 'a(1, 2, 3)\n' > expected.rb
+    diff_files o out.rb expected.rb
+    )
+}
+
+test_rubyfmt_config_env() {
+    (
+    cd "$(mktemp -d)"
+
+    printf "things = [\n  first,\n  second\n]\nputs('hi')\n" > input.rb
+    printf 'things = [\n    first,\n    second\n]\nputs("hi")\n' > indented.rb
+    printf "things = [\n    first,\n    second\n]\nputs('hi')\n" > indented_kept_strings.rb
+    printf "things = [\n  first,\n  second\n]\nputs('hi')\n" > kept_strings.rb
+
+    # RUBYFMT_CONFIG can be the path to a TOML file
+    cat > config.toml <<- TOML
+# Wider continuations
+continuation_indent = 4
+skip-string-normalization = false
+TOML
+    RUBYFMT_CONFIG=config.toml f_rubyfmt -- input.rb > out.rb
+    diff_files o out.rb indented.rb
+
+    # or the TOML itself
+    RUBYFMT_CONFIG='continuation_indent = 4
+skip_string_normalization = true' f_rubyfmt -- input.rb > out.rb
+    diff_files o out.rb indented_kept_strings.rb
+
+    # Options given on the command line win over it, and the rest of it
+    # still applies
+    RUBYFMT_CONFIG='continuation_indent = 4
+skip_string_normalization = true' f_rubyfmt --continuation-indent 2 -- input.rb > out.rb
+    diff_files o out.rb kept_strings.rb
+
+    RUBYFMT_CONFIG=config.toml f_rubyfmt --continuation-indent=2 --skip-string-normalization -- input.rb > out.rb
+    diff_files o out.rb kept_strings.rb
+
+    # Unknown options are an error
+    if RUBYFMT_CONFIG='line_width = 80' f_rubyfmt -- input.rb 2> err.txt; then
+        echo "expected an unknown RUBYFMT_CONFIG option to fail"
+        exit 1
+    fi
+    grep -q "unknown option \`line-width\`" err.txt
+    )
+}
+
+test_blank_files() {
+    (
+    cd "$(mktemp -d)"
+
+    printf '' > empty.rb
+    printf '  \n\n\t\n' > whitespace.rb
+    f_rubyfmt -i -- empty.rb whitespace.rb
+    if [ -s empty.rb ] || [ -s whitespace.rb ]; then
+        echo "expected blank files to be formatted as empty files"
+        exit 1
+    fi
+    f_rubyfmt --check -- empty.rb whitespace.rb
+
+    printf '\n\n' | f_rubyfmt --no-final-newline-normalization > out.rb
+    printf '\n\n' > expected.rb
+    diff_files o out.rb expected.rb
+    )
+}
+
+test_selfcheck() {
+    (
+    f_rubyfmt selfcheck "$REPO_BASE/fixtures/small" > /tmp/selfcheck_out.txt
+    tail -n 1 /tmp/selfcheck_out.txt | grep -q "files, 0 weren't idempotent$"
+    )
+}
+
+test_files_from() {
+    (
+    cd "$(mktemp -d)"
+    echo "a 1, 2, 3" > a_ruby_file_1.rb
+    echo "b 4, 5" > a_ruby_file_2.rb
+    echo "c 6" > not_listed.rb
+    cat > manifest.txt <<- MANIFEST
+# changed files
+a_ruby_file_1.rb
+
+a_ruby_file_2.rb
+MANIFEST
+
+    f_rubyfmt -i --files-from manifest.txt
+    echo "a(1, 2, 3)" > a_ruby_file_1_expected.rb
+    echo "b(4, 5)" > a_ruby_file_2_expected.rb
+    echo "c 6" > not_listed_expected.rb
+    diff_files o a_ruby_file_1_expected.rb a_ruby_file_1.rb
+    diff_files o a_ruby_file_2_expected.rb a_ruby_file_2.rb
+    diff_files o not_listed_expected.rb not_listed.rb
+
+    # `-` reads the manifest from STDIN
+    echo "c 6" > not_listed.rb
+    echo "not_listed.rb" | f_rubyfmt -i --files-from -
+    echo "c(6)" > not_listed_expected.rb
+    diff_files o not_listed_expected.rb not_listed.rb
+
+    # `@file` input files are read the same way
+    echo "a 1, 2, 3" > a_ruby_file_1.rb
+    echo "b 4, 5" > a_ruby_file_2.rb
+    f_rubyfmt -i -- @manifest.txt
+    diff_files o a_ruby_file_1_expected.rb a_ruby_file_1.rb
+    diff_files o a_ruby_file_2_expected.rb a_ruby_file_2.rb
+    )
+}
+
 test_simple_stdout
 test_i_flag
 test_i_flag_no_changes
@@ -672,3 +1055,16 @@ test_respects_gitignore
 test_includes_gitignore
 
 test_respects_rubyfmt_ignore_file
+
+test_jobs_flag_output_is_deterministic
+test_verbose_flag
+test_log_level
+test_glob_patterns
+test_profile_flag
+test_json_report
+test_fragment_kinds
+test_no_final_newline_normalization
+test_rubyfmt_config_env
+test_blank_files
+test_files_from
+test_selfcheck
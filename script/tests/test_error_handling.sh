@@ -71,6 +71,61 @@ test_syntax_error_files_fail_fast() {
     )
 }
 
+test_incomplete_parse_file() {
+    (
+    cd "$(mktemp -d)"
+
+    # Ruby stops reading at the ^D, which would drop `b(2)` from the output
+    printf 'a(1)\n\x04\nb(2)\n' > file.rb
+    set +e
+    f_rubyfmt --fail-fast -- file.rb 2> err.txt
+    status=$?
+    set -e
+
+    if [ "$status" -ne 6 ]
+    then
+        echo "expected an incomplete parse error, got exit code $status"
+        exit 1
+    fi
+
+    if ! grep -q "stopped reading the file at line 2" err.txt
+    then
+        echo "incomplete parse error didn't name the line"
+        exit 1
+    fi
+    )
+}
+
+test_verbose_errors_snippet() {
+    (
+    cd "$(mktemp -d)"
+
+    printf 'a = 1\nfoo(1,, 2)\n' > file.rb
+    if f_rubyfmt --fail-fast --verbose-errors -- file.rb 2> err.txt
+    then
+        echo "rubyfmt didn't break as expected"
+        exit 1
+    fi
+
+    # The caret goes under the second comma
+    grep -q "^2 | foo(1,, 2)$" err.txt
+    if ! grep -q "^  |       ^ " err.txt
+    then
+        echo "the caret isn't under the column of the error"
+        cat err.txt
+        exit 1
+    fi
+
+    # Without the flag there's no snippet
+    f_rubyfmt --fail-fast -- file.rb 2> err.txt || true
+    if grep -q "foo(1,, 2)" err.txt
+    then
+        echo "printed a snippet without --verbose-errors"
+        exit 1
+    fi
+    )
+}
+
 test_io_error_file() {
     (
     cd "$(mktemp -d)"
@@ -142,6 +197,8 @@ test_syntax_error_file
 test_syntax_error_files
 test_syntax_error_file_fail_fast
 test_syntax_error_files_fail_fast
+test_incomplete_parse_file
+test_verbose_errors_snippet
 
 test_io_error_file
 test_io_error_files
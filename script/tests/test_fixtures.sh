@@ -8,3 +8,21 @@ make
 
 test_fixtures_folder "fixtures/small"
 test_fixtures_folder "fixtures/large"
+
+# Fixtures for non-default options, formatted with the flags they're named after
+test_fixtures_folder "fixtures/options/mixin_spacing_always" --mixin-spacing always
+test_fixtures_folder "fixtures/options/mixin_spacing_never" --mixin-spacing never
+test_fixtures_folder "fixtures/options/continuation_indent_4" --continuation-indent 4
+test_fixtures_folder "fixtures/options/skip_string_normalization" --skip-string-normalization
+test_fixtures_folder "fixtures/options/inline_rescue_expand" --inline-rescue expand
+test_fixtures_folder "fixtures/options/comment_indent_keep" --comment-indent keep
+test_fixtures_folder "fixtures/options/blank_lines_between_defs_2" --blank-lines-between-defs 2 --blank-lines-between-nested-defs 1
+test_fixtures_folder "fixtures/options/condition_parens_strip" --condition-parens strip
+test_fixtures_folder "fixtures/options/align_hash_rows" --align-hash-rows
+test_fixtures_folder "fixtures/options/sort_hash_keys" --sort-hash-keys
+test_fixtures_folder "fixtures/options/case_when_indent_indented" --case-when-indent indented
+test_fixtures_folder "fixtures/options/class_body_style_compact" --class-body-style compact
+
+# Every option that rewrites code at once, `format_with_transforms` has a
+# doctest checking what it reports for these
+test_fixtures_folder "fixtures/options/opt_in_transforms" --sort-hash-keys --align-hash-rows --condition-parens strip --inline-rescue expand
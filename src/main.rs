@@ -1,16 +1,21 @@
 #![deny(warnings, missing_copy_implementations)]
 
 mod updates;
-use clap::Parser;
+use clap::{ArgEnum, CommandFactory, ErrorKind, Parser, Subcommand};
 use ignore::WalkBuilder;
+use log::info;
 use regex::Regex;
 use similar::TextDiff;
-use std::ffi::OsStr;
+use std::env;
+use std::ffi::{OsStr, OsString};
 use std::fs::{read_to_string, File, OpenOptions};
 use std::io::{self, BufRead, BufReader, Read, Write};
-use std::path::Path;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::sync::mpsc;
 use std::sync::{Arc, Mutex};
+use std::thread;
 
 #[macro_use]
 extern crate lazy_static;
@@ -18,6 +23,8 @@ extern crate lazy_static;
 lazy_static! {
     static ref MAGIC_COMMENT_REGEX: Regex =
         Regex::new(r"(?m)^#\s*rubyfmt:\s*(?P<enabled>true|false)\s*$").unwrap();
+    static ref PROFILE: Mutex<rubyfmt::Profile> = Mutex::new(rubyfmt::Profile::default());
+    static ref REPORT: Mutex<Vec<FileReport>> = Mutex::new(Vec::new());
 }
 
 /// Simple Enum to exit on errors or not
@@ -31,13 +38,41 @@ enum ErrorExit {
 #[derive(Debug)]
 enum ExecutionError {
     // Errors seen when rubyfmt is executing
-    RubyfmtError(rubyfmt::RichFormatError, String),
+    // along with the lines where it happened, for --verbose-errors
+    RubyfmtError(rubyfmt::RichFormatError, String, Option<String>),
     // Errors seen when performing IO s
     IOError(io::Error, String),
     // Errors seen when grepping for files
     FileSearchFailure(ignore::Error),
 }
 
+/// Machine readable formats for `--report`
+#[derive(Debug, PartialEq, Copy, Clone, ArgEnum)]
+enum ReportFormat {
+    Json,
+}
+
+/// Commands that do something other than format files
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Format every ruby file in the given files and directories twice, and print a diff for each one that changes when it's formatted a second time. For checking rubyfmt against a codebase before adopting it. Exits non-zero if any file isn't idempotent.
+    Selfcheck {
+        /// Files or directories to check, accepted in the same forms as the paths to format.
+        #[clap(required = true)]
+        paths: Vec<String>,
+    },
+}
+
+/// What happened to a single file, for `--report`
+#[derive(Debug, serde::Serialize)]
+struct FileReport {
+    path: String,
+    changed: bool,
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diff: Option<String>,
+}
+
 /// Rubyfmt CLI
 #[derive(Debug, Parser)]
 #[clap(author, version, about, long_about = None)]
@@ -74,14 +109,102 @@ struct CommandlineOpts {
     #[clap(short, long, name = "in-place")]
     in_place: bool,
 
+    /// Number of worker threads used to read files. Defaults to the number of logical CPUs, so files are read in parallel unless this is `-j1`. Files are always formatted one at a time, in the same order.
+    #[clap(short, long)]
+    jobs: Option<NonZeroUsize>,
+
+    /// Log what rubyfmt did with each file, and why blank lines were inserted, to STDERR.
+    #[clap(long)]
+    verbose: bool,
+
+    /// The most verbose diagnostics to print to STDERR. `info` is the same as `--verbose`, and `debug` traces the formatter itself. Defaults to `warn`.
+    #[clap(long, name = "log-level", possible_values = &["error", "warn", "info", "debug"])]
+    log_level: Option<log::LevelFilter>,
+
+    /// When a file can't be formatted because of a syntax error, also print the lines around it to STDERR, with a caret under where it is.
+    #[clap(long, name = "verbose-errors")]
+    verbose_errors: bool,
+
+    /// Whether to put a blank line between a `class`/`module` line and the `include`/`extend`/`prepend` calls directly after it.
+    #[clap(long, name = "mixin-spacing", default_value = "preserve", possible_values = &["preserve", "always", "never"])]
+    mixin_spacing: rubyfmt::MixinSpacing,
+
+    /// Number of spaces to indent the lines of broken collections, argument lists and method chains by.
+    #[clap(long, name = "continuation-indent", default_value = "2")]
+    continuation_indent: u32,
+
+    /// Keep the newlines at the end of the input exactly as they are, instead of always ending the output with a single newline.
+    #[clap(long, name = "no-final-newline-normalization")]
+    no_final_newline_normalization: bool,
+
+    /// Leave string literals as they were written, instead of converting them to double quoted strings.
+    #[clap(long, name = "skip-string-normalization")]
+    skip_string_normalization: bool,
+
+    /// Whether to keep one-line `rescue` modifiers on statements and assignments, or expand them into `begin` blocks.
+    #[clap(long, name = "inline-rescue", default_value = "preserve", possible_values = &["preserve", "expand"])]
+    inline_rescue: rubyfmt::InlineRescue,
+
+    /// Whether every comment line is indented to the level of the code around it, or the lines of a comment block keep their indentation relative to its first line.
+    #[clap(long, name = "comment-indent", default_value = "normalize", possible_values = &["normalize", "keep"])]
+    comment_indent: rubyfmt::CommentIndent,
+
+    /// Number of blank lines between top level methods.
+    #[clap(long, name = "blank-lines-between-defs", default_value = "1")]
+    blank_lines_between_defs: u32,
+
+    /// Number of blank lines between methods inside a class, module or block.
+    #[clap(long, name = "blank-lines-between-nested-defs", default_value = "1")]
+    blank_lines_between_nested_defs: u32,
+
+    /// Whether to keep or remove the parens around the condition of an `if`, `unless`, `elsif`, `while` or `until`, when removing them doesn't change what the condition means.
+    #[clap(long, name = "condition-parens", default_value = "keep", possible_values = &["keep", "strip"])]
+    condition_parens: rubyfmt::ConditionParens,
+
+    /// Line up the entries of the hashes in a multiline array of single line hashes with the same keys, like a table.
+    #[clap(long, name = "align-hash-rows")]
+    align_hash_rows: bool,
+
+    /// Sort the entries of hashes annotated with a `# rubyfmt:sort-keys` comment by key.
+    #[clap(long, name = "sort-hash-keys")]
+    sort_hash_keys: bool,
+
+    /// Whether the `when` and `else` lines of a `case` line up with the `case`, or are indented one level past it.
+    #[clap(long, name = "case-when-indent", default_value = "aligned", possible_values = &["aligned", "indented"])]
+    case_when_indent: rubyfmt::CaseWhenIndent,
+
+    /// Whether class and module bodies that are empty or a single statement go on one line, like `class Foo; BAR = 1; end`.
+    #[clap(long, name = "class-body-style", default_value = "expanded", possible_values = &["expanded", "compact"])]
+    class_body_style: rubyfmt::ClassBodyStyle,
+
+    /// Print the time spent in each phase of formatting, summed over all files, to STDERR.
+    #[clap(long)]
+    profile: bool,
+
+    /// Print a report of what happened to every file to STDOUT once all files are processed, instead of the usual output.
+    #[clap(long, arg_enum)]
+    report: Option<ReportFormat>,
+
+    /// What the input read from STDIN is expected to be, for formatting a selection in an editor.
+    #[clap(long, name = "fragment-kind", default_value = "file", possible_values = &["file", "expression", "method-body"])]
+    fragment_kind: rubyfmt::FragmentKind,
+
+    /// Read the paths to format from this file, or from STDIN if it's `-`. The file is read like an `@file` input file, see `include-paths`.
+    #[clap(long, name = "files-from")]
+    files_from: Option<String>,
+
     /// Paths for rubyfmt to analyze. By default the output will be printed to STDOUT. See `--in-place` to write files back in-place.
     /// Acceptable paths are:{n}
     /// - File paths (i.e lib/foo/bar.rb){n}
     /// - Directories (i.e. lib/foo/){n}
     /// - Input files (i.e. @/tmp/files.txt). These files must contain one file path or directory per line
-    /// rubyfmt will use these as input.{n}
+    /// rubyfmt will use these as input. Blank lines and lines starting with `#` are ignored.{n}
+    /// - Glob patterns (i.e. "lib/**/*.rb"), for shells that don't expand them.{n}
     #[clap(name = "include-paths", last = true)]
     include_paths: Vec<String>,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
 }
 
 /******************************************************/
@@ -105,18 +228,35 @@ fn handle_ignore_error(err: ignore::Error, error_exit: ErrorExit) {
     }
 }
 
-fn handle_rubyfmt_error(err: rubyfmt::RichFormatError, source: &String, error_exit: ErrorExit) {
+fn handle_rubyfmt_error(
+    err: rubyfmt::RichFormatError,
+    source: &String,
+    context: Option<String>,
+    error_exit: ErrorExit,
+) {
     use rubyfmt::RichFormatError::*;
     let exit_code = err.as_exit_code();
     let e = || {
+        if let Some(context) = &context {
+            eprint!("{}", context);
+        }
         if error_exit == ErrorExit::Exit {
             exit(exit_code);
         }
     };
     match err {
-        SyntaxError => {
-            let msg = "Rubyfmt detected a syntax error in the ruby code being executed";
-            print_error(msg, Some(source));
+        SyntaxError(location) => {
+            let mut msg =
+                "Rubyfmt detected a syntax error in the ruby code being executed".to_string();
+            if let Some(location) = location {
+                msg.push_str(&format!(
+                    " at line {}, column {}: {}",
+                    location.line,
+                    location.column + 1,
+                    location.message
+                ));
+            }
+            print_error(&msg, Some(source));
             e();
         }
         rubyfmt::RichFormatError::RipperParseFailure(_) => {
@@ -142,6 +282,19 @@ with the debug binary with `2>log_file` on the end and then send us the log file
             print_error(&msg, Some(source));
             exit(exit_code);
         }
+        FragmentKindMismatch(s) => {
+            let msg = format!("The input doesn't match the given --fragment-kind: {}", s);
+            print_error(&msg, Some(source));
+            e();
+        }
+        IncompleteParse(line) => {
+            let msg = format!(
+                "Ruby stopped reading the file at line {}, there's likely a NUL, ^D or ^Z byte there. Rubyfmt won't format it, since everything after it would be dropped",
+                line
+            );
+            print_error(&msg, Some(source));
+            e();
+        }
     }
 }
 
@@ -156,25 +309,112 @@ fn print_error(msg: &str, file_path: Option<&str>) {
 }
 
 fn handle_execution_error(opts: &CommandlineOpts, err: ExecutionError) {
+    match &err {
+        ExecutionError::RubyfmtError(e, path, _) => {
+            record_error(opts, path, describe_rubyfmt_error(e))
+        }
+        ExecutionError::IOError(e, path) => record_error(opts, path, e.to_string()),
+        ExecutionError::FileSearchFailure(_) => {}
+    }
+
     let mut exit_type = ErrorExit::NoExit;
     // If include_paths are empty, this is operating on STDIN which should always exit
     if opts.fail_fast || opts.include_paths.is_empty() {
         exit_type = ErrorExit::Exit;
     }
 
+    // Unexpected ruby errors always exit, and the report has to come out
+    // before that since it's most useful when something went wrong
+    let exits = exit_type == ErrorExit::Exit
+        || matches!(
+            err,
+            ExecutionError::RubyfmtError(rubyfmt::RichFormatError::OtherRubyError(_), _, _)
+        );
+    if exits {
+        print_report(opts);
+    }
+
     match err {
-        ExecutionError::RubyfmtError(e, path) => handle_rubyfmt_error(e, &path, exit_type),
+        ExecutionError::RubyfmtError(e, path, context) => {
+            handle_rubyfmt_error(e, &path, context, exit_type)
+        }
         ExecutionError::IOError(e, path) => handle_io_error(e, &path, exit_type),
         ExecutionError::FileSearchFailure(e) => handle_ignore_error(e, exit_type),
     }
 }
 
+fn describe_rubyfmt_error(err: &rubyfmt::RichFormatError) -> String {
+    use rubyfmt::RichFormatError::*;
+    match err {
+        SyntaxError(_) => "syntax error".to_string(),
+        RipperParseFailure(e) => format!("ripper tree deserialization error: {}", e),
+        IOError(e) => format!("io error: {}", e),
+        OtherRubyError(s) => format!("ruby error: {}", s),
+        FragmentKindMismatch(s) => format!("fragment kind mismatch: {}", s),
+        IncompleteParse(line) => format!("incomplete parse, stopped at line {}", line),
+    }
+}
+
+/******************************************************/
+/* Reporting                                          */
+/******************************************************/
+
+fn record_error(opts: &CommandlineOpts, path: &str, error: String) {
+    if opts.report.is_some() {
+        REPORT.lock().unwrap().push(FileReport {
+            path: path.to_string(),
+            changed: false,
+            error: Some(error),
+            diff: None,
+        });
+    }
+}
+
+fn record_formatted(
+    opts: &CommandlineOpts,
+    file_path: &Path,
+    before: &str,
+    after: &Option<String>,
+) {
+    if opts.report.is_none() {
+        return;
+    }
+
+    let path = file_path.display().to_string();
+    let diff = match after {
+        Some(fmtted) if fmtted != before => Some(format!(
+            "{}",
+            TextDiff::from_lines(before, fmtted)
+                .unified_diff()
+                .header(&path, &path)
+        )),
+        _ => None,
+    };
+    REPORT.lock().unwrap().push(FileReport {
+        path,
+        changed: diff.is_some(),
+        error: None,
+        diff,
+    });
+}
+
+fn print_report(opts: &CommandlineOpts) {
+    match opts.report {
+        None => {}
+        Some(ReportFormat::Json) => {
+            let report = REPORT.lock().unwrap();
+            let json = serde_json::to_string_pretty(&*report).expect("reports always serialize");
+            puts_stdout(&format!("{}\n", json));
+        }
+    }
+}
+
 /******************************************************/
 /* Rubyfmt Integration                                */
 /******************************************************/
 
 fn rubyfmt_string(
-    &CommandlineOpts {
+    opts @ &CommandlineOpts {
         header_opt_in,
         header_opt_out,
         ..
@@ -213,10 +453,35 @@ fn rubyfmt_string(
         }
     }
 
-    rubyfmt::format_buffer(buffer).map(Some)
+    if opts.fragment_kind != rubyfmt::FragmentKind::File {
+        return rubyfmt::format_fragment(buffer, opts.fragment_kind, &rubyfmt_config(opts))
+            .map(Some);
+    }
+
+    let (output, profile) = rubyfmt::format_buffer_profiled(buffer, &rubyfmt_config(opts))?;
+    *PROFILE.lock().unwrap() += profile;
+    Ok(Some(output))
 }
 
-fn initialize_rubyfmt() {
+fn rubyfmt_config(opts: &CommandlineOpts) -> rubyfmt::Config {
+    rubyfmt::Config::builder()
+        .mixin_spacing(opts.mixin_spacing)
+        .continuation_indent(opts.continuation_indent)
+        .normalize_final_newline(!opts.no_final_newline_normalization)
+        .normalize_strings(!opts.skip_string_normalization)
+        .inline_rescue(opts.inline_rescue)
+        .comment_indent(opts.comment_indent)
+        .blank_lines_between_defs(opts.blank_lines_between_defs)
+        .blank_lines_between_nested_defs(opts.blank_lines_between_nested_defs)
+        .condition_parens(opts.condition_parens)
+        .align_hash_rows(opts.align_hash_rows)
+        .sort_hash_keys(opts.sort_hash_keys)
+        .case_when_indent(opts.case_when_indent)
+        .class_body_style(opts.class_body_style)
+        .build()
+}
+
+fn initialize_rubyfmt(opts: &CommandlineOpts) {
     let res = rubyfmt::rubyfmt_init();
     if res != rubyfmt::InitStatus::OK as libc::c_int {
         panic!(
@@ -224,6 +489,13 @@ fn initialize_rubyfmt() {
             rubyfmt::ruby::current_exception_as_rust_string()
         );
     }
+
+    if let Some(level) = opts.log_level {
+        rubyfmt::set_log_level(level);
+    }
+    if opts.verbose {
+        rubyfmt::enable_verbose_logging();
+    }
 }
 
 /******************************************************/
@@ -252,30 +524,251 @@ fn file_walker_builder(
     builder
 }
 
-// Parse command line arguments. Expand any input files.
+fn worker_count(opts: &CommandlineOpts) -> usize {
+    opts.jobs
+        .or_else(|| thread::available_parallelism().ok())
+        .map(NonZeroUsize::get)
+        .unwrap_or(1)
+}
+
+fn is_ruby_file(file_path: &Path) -> bool {
+    file_path.is_file() && file_path.extension().and_then(OsStr::to_str) == Some("rb")
+}
+
+type ReadResult = Result<(PathBuf, io::Result<String>), ignore::Error>;
+
+// A ruby file found by the walk, and where its contents will be sent once
+// a worker has read them
+type PendingRead = Result<(PathBuf, mpsc::Receiver<io::Result<String>>), ignore::Error>;
+
+// Walks the include paths in the same path sorted order as a sequential run,
+// and reads the ruby files on `jobs` worker threads. The ruby VM can only be
+// used from the main thread, so `f` is called there, in walk order, for each
+// file as soon as it has been read, and for each walk error where the walk
+// hit it. At most `jobs` files are read ahead of the one `f` is called with.
+fn read_files_in_parallel(opts: &CommandlineOpts, jobs: usize, mut f: impl FnMut(ReadResult)) {
+    let mut builder = file_walker_builder(opts);
+    builder.sort_by_file_path(|a, b| a.cmp(b));
+
+    let (read_tx, read_rx) = mpsc::channel::<(PathBuf, mpsc::SyncSender<io::Result<String>>)>();
+    let read_rx = Mutex::new(read_rx);
+    let (pending_tx, pending_rx) = mpsc::sync_channel::<PendingRead>(jobs);
+
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| loop {
+                let next = read_rx.lock().unwrap().recv();
+                match next {
+                    Ok((file_path, contents_tx)) => {
+                        // The main thread only stops listening when it exits
+                        let _ = contents_tx.send(read_to_string(&file_path));
+                    }
+                    Err(_) => break,
+                }
+            });
+        }
+
+        scope.spawn(move || {
+            for result in builder.build() {
+                let pending = match result {
+                    Ok(pp) if !is_ruby_file(pp.path()) => continue,
+                    Ok(pp) => {
+                        let (contents_tx, contents_rx) = mpsc::sync_channel(1);
+                        let file_path = pp.into_path();
+                        read_tx
+                            .send((file_path.clone(), contents_tx))
+                            .expect("workers outlive the walk");
+                        Ok((file_path, contents_rx))
+                    }
+                    Err(e) => Err(e),
+                };
+                if pending_tx.send(pending).is_err() {
+                    break;
+                }
+            }
+        });
+
+        for pending in pending_rx {
+            f(pending.map(|(file_path, contents_rx)| {
+                let buffer_res = contents_rx.recv().expect("workers read every file");
+                (file_path, buffer_res)
+            }));
+        }
+    });
+}
+
+// Expands a glob pattern into the paths it matches, for shells (i.e. cmd.exe)
+// that pass patterns through unexpanded. Anything that exists as a literal
+// path, isn't a valid pattern, or matches nothing is passed through as is.
+fn expand_glob(path: String) -> Vec<String> {
+    if !path.contains(&['*', '?', '['][..]) || Path::new(&path).exists() {
+        return vec![path];
+    }
+
+    let matches: Vec<String> = match glob::glob(&path) {
+        Ok(paths) => paths
+            .filter_map(Result::ok)
+            .map(|p| p.display().to_string())
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+
+    if matches.is_empty() {
+        vec![path]
+    } else {
+        matches
+    }
+}
+
+// The formatting options that can be set in `RUBYFMT_CONFIG`, and whether
+// they're flags that don't take a value.
+const CONFIG_OPTIONS: &[(&str, bool)] = &[
+    ("mixin-spacing", false),
+    ("continuation-indent", false),
+    ("no-final-newline-normalization", true),
+    ("skip-string-normalization", true),
+    ("inline-rescue", false),
+    ("comment-indent", false),
+    ("blank-lines-between-defs", false),
+    ("blank-lines-between-nested-defs", false),
+    ("condition-parens", false),
+    ("align-hash-rows", true),
+    ("sort-hash-keys", true),
+    ("case-when-indent", false),
+    ("class-body-style", false),
+];
+
+// The command line options set by `toml`, which is a flat TOML table keyed
+// by option name, i.e. `continuation_indent = 4` or `align-hash-rows = true`.
+fn config_args(toml: &str) -> Result<Vec<(&'static str, String)>, String> {
+    let mut args = Vec::new();
+    for (idx, line) in toml.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected `key = value`", idx + 1))?;
+        let key = key.trim().replace('_', "-");
+        let &(option, is_flag) = CONFIG_OPTIONS
+            .iter()
+            .find(|(option, _)| *option == key)
+            .ok_or_else(|| format!("line {}: unknown option `{}`", idx + 1, key))?;
+
+        let value = value.trim();
+        let value = match value.strip_prefix('"') {
+            Some(quoted) => quoted
+                .split_once('"')
+                .filter(|(_, rest)| rest.trim().is_empty() || rest.trim().starts_with('#'))
+                .map(|(string, _)| string)
+                .ok_or_else(|| format!("line {}: unterminated string", idx + 1))?,
+            None => value.split('#').next().unwrap_or_default().trim(),
+        };
+        match (is_flag, value) {
+            (true, "true") => args.push((option, format!("--{}", option))),
+            (true, "false") => {}
+            (true, _) => {
+                return Err(format!(
+                    "line {}: `{}` must be true or false",
+                    idx + 1,
+                    option
+                ))
+            }
+            (false, _) => args.push((option, format!("--{}={}", option, value))),
+        }
+    }
+    Ok(args)
+}
+
+// The command line arguments, with the options set in `RUBYFMT_CONFIG`
+// before them. That's either the path to a TOML file or the TOML itself.
+// Options given on the command line win over the ones set there.
+fn args_with_env_config() -> Vec<OsString> {
+    let mut args: Vec<OsString> = env::args_os().collect();
+    let config = match env::var("RUBYFMT_CONFIG") {
+        Ok(config) if !config.trim().is_empty() => config,
+        _ => return args,
+    };
+
+    let toml = if Path::new(&config).is_file() {
+        match read_to_string(&config) {
+            Ok(toml) => toml,
+            Err(e) => {
+                handle_io_error(e, &config, ErrorExit::Exit);
+                unreachable!()
+            }
+        }
+    } else {
+        config
+    };
+    let config_args = config_args(&toml).unwrap_or_else(|e| {
+        CommandlineOpts::command()
+            .error(ErrorKind::InvalidValue, format!("RUBYFMT_CONFIG {}", e))
+            .exit()
+    });
+
+    let given = |option: &str| {
+        let flag = format!("--{}", option);
+        args.iter()
+            .skip(1)
+            .map(|arg| arg.to_string_lossy())
+            .take_while(|arg| arg != "--")
+            .any(|arg| arg == flag || arg.starts_with(&format!("{}=", flag)))
+    };
+    let from_config: Vec<OsString> = config_args
+        .into_iter()
+        .filter(|(option, _)| !given(option))
+        .map(|(_, arg)| arg.into())
+        .collect();
+    args.splice(1..1, from_config);
+    args
+}
+
+// Parse command line arguments. Expand any input files and glob patterns.
 fn get_command_line_options() -> CommandlineOpts {
-    let opts = CommandlineOpts::parse();
+    let mut opts = CommandlineOpts::parse_from(args_with_env_config());
+
+    // `selfcheck` walks its paths exactly like the paths to format
+    if let Some(Command::Selfcheck { paths }) = &mut opts.command {
+        opts.include_paths.append(paths);
+    }
+
+    if opts.fragment_kind != rubyfmt::FragmentKind::File
+        && (!opts.include_paths.is_empty() || opts.files_from.is_some())
+    {
+        CommandlineOpts::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--fragment-kind can only be used when reading from STDIN",
+            )
+            .exit();
+    }
 
     let mut expanded_paths: Vec<String> = Vec::new();
 
+    if let Some(input_file) = &opts.files_from {
+        if input_file == "-" {
+            match read_input_file(io::stdin().lock()) {
+                Ok(mut paths) => expanded_paths.append(&mut paths),
+                Err(e) => handle_io_error(e, input_file, ErrorExit::Exit),
+            }
+        } else {
+            expanded_paths.append(&mut expand_input_file(input_file, input_file));
+        }
+
+        // No paths would otherwise mean formatting STDIN
+        if expanded_paths.is_empty() && opts.include_paths.is_empty() {
+            exit(0);
+        }
+    }
+
     for path in opts.include_paths {
         // Expand input files
         if let Some(file_name) = path.strip_prefix('@') {
-            match File::open(file_name) {
-                Ok(file) => {
-                    let buf = BufReader::new(file);
-                    let lines: Vec<String> = buf
-                        .lines()
-                        .map(|l| l.expect("Could not parse line"))
-                        .collect();
-                    for line in lines {
-                        expanded_paths.push(line);
-                    }
-                }
-                Err(e) => handle_io_error(e, &path, ErrorExit::Exit),
-            }
+            expanded_paths.append(&mut expand_input_file(file_name, &path));
         } else {
-            expanded_paths.push(path);
+            expanded_paths.append(&mut expand_glob(path));
         }
     }
 
@@ -285,6 +778,32 @@ fn get_command_line_options() -> CommandlineOpts {
     }
 }
 
+// The paths listed in an input file, given as `@file` or `--files-from file`.
+// `source` is how the file was given, for errors.
+fn expand_input_file(file_name: &str, source: &String) -> Vec<String> {
+    match File::open(file_name).and_then(|file| read_input_file(BufReader::new(file))) {
+        Ok(paths) => paths,
+        Err(e) => {
+            handle_io_error(e, source, ErrorExit::Exit);
+            Vec::new()
+        }
+    }
+}
+
+// Input files have one path per line. Blank lines and lines starting with `#`
+// are skipped, so that a list can be commented.
+fn read_input_file(reader: impl BufRead) -> io::Result<Vec<String>> {
+    let mut paths = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let path = line.trim();
+        if !path.is_empty() && !path.starts_with('#') {
+            paths.push(path.to_string());
+        }
+    }
+    Ok(paths)
+}
+
 fn iterate_input_files(opts: &CommandlineOpts, f: &dyn Fn((&Path, &String))) {
     if opts.include_paths.is_empty() {
         // If not include paths are present, assume user is passing via STDIN
@@ -294,27 +813,36 @@ fn iterate_input_files(opts: &CommandlineOpts, f: &dyn Fn((&Path, &String))) {
             .expect("reading from stdin to not fail");
         f((Path::new("stdin"), &buffer))
     } else {
-        for result in file_walker_builder(opts).build() {
-            match result {
-                Ok(pp) => {
-                    let file_path = pp.path();
-
-                    if file_path.is_file()
-                        && file_path.extension().and_then(OsStr::to_str) == Some("rb")
-                    {
-                        let buffer_res = read_to_string(file_path);
-
-                        match buffer_res {
-                            Ok(buffer) => f((file_path, &buffer)),
-                            Err(e) => handle_execution_error(
-                                opts,
-                                ExecutionError::IOError(e, file_path.display().to_string()),
-                            ),
+        let jobs = worker_count(opts);
+        let handle_read = |file_path: &Path, buffer_res: io::Result<String>| match buffer_res {
+            Ok(buffer) => f((file_path, &buffer)),
+            Err(e) => handle_execution_error(
+                opts,
+                ExecutionError::IOError(e, file_path.display().to_string()),
+            ),
+        };
+
+        if jobs == 1 {
+            let mut builder = file_walker_builder(opts);
+            builder.sort_by_file_path(|a, b| a.cmp(b));
+
+            for result in builder.build() {
+                match result {
+                    Ok(pp) => {
+                        let file_path = pp.path();
+
+                        if is_ruby_file(file_path) {
+                            handle_read(file_path, read_to_string(file_path));
                         }
                     }
+                    Err(e) => handle_execution_error(opts, ExecutionError::FileSearchFailure(e)),
                 }
-                Err(e) => handle_execution_error(opts, ExecutionError::FileSearchFailure(e)),
             }
+        } else {
+            read_files_in_parallel(opts, jobs, |result| match result {
+                Ok((file_path, buffer_res)) => handle_read(&file_path, buffer_res),
+                Err(e) => handle_execution_error(opts, ExecutionError::FileSearchFailure(e)),
+            });
         }
     }
 }
@@ -325,15 +853,45 @@ fn iterate_formatted(opts: &CommandlineOpts, f: FormattingFunc) {
     iterate_input_files(
         opts,
         &|(file_path, before)| match rubyfmt_string(opts, before) {
-            Ok(r) => f((file_path, before, r)),
-            Err(e) => handle_execution_error(
-                opts,
-                ExecutionError::RubyfmtError(e, file_path.display().to_string()),
-            ),
+            Ok(r) => {
+                let outcome = match &r {
+                    None => "skipped because of its rubyfmt header",
+                    Some(fmtted) if fmtted == before => "already formatted",
+                    Some(_) => "formatted",
+                };
+                info!("{}: {}", file_path.display(), outcome);
+                record_formatted(opts, file_path, before, &r);
+                f((file_path, before, r))
+            }
+            Err(e) => {
+                let context = if opts.verbose_errors {
+                    e.source_context(before)
+                } else {
+                    None
+                };
+                handle_execution_error(
+                    opts,
+                    ExecutionError::RubyfmtError(e, file_path.display().to_string(), context),
+                )
+            }
         },
     );
 }
 
+fn report_profile(opts: &CommandlineOpts) {
+    if !opts.profile {
+        return;
+    }
+
+    let profile = PROFILE.lock().unwrap();
+    let mut table = format!("{:<14}{:>12}\n", "phase", "time");
+    for (phase, duration) in profile.phases().iter() {
+        table.push_str(&format!("{:<14}{:>12.3?}\n", phase, duration));
+    }
+    table.push_str(&format!("{:<14}{:>12.3?}", "total", profile.total()));
+    eprintln!("{}", table);
+}
+
 fn puts_stdout(input: &String) {
     write!(io::stdout(), "{}", input).expect("Could not write to stdout");
     io::stdout().flush().expect("flush works");
@@ -350,8 +908,59 @@ fn main() {
             ..
         } => updates::fetch_latest_version().unwrap(),
 
+        CommandlineOpts {
+            command: Some(Command::Selfcheck { .. }),
+            ..
+        } => {
+            initialize_rubyfmt(&opts);
+            let checked: Arc<Mutex<usize>> = Arc::new(Mutex::new(0));
+            let text_diffs: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(Vec::new()));
+
+            iterate_formatted(&opts, &|(file_path, _, after)| match after {
+                None => {}
+                Some(fmtted) => {
+                    *checked.lock().unwrap() += 1;
+                    match rubyfmt::format_buffer_with_config(&fmtted, &rubyfmt_config(&opts)) {
+                        Ok(refmtted) if refmtted == fmtted => {}
+                        Ok(refmtted) => {
+                            let diff = TextDiff::from_lines(&fmtted, &refmtted);
+                            let path_string = file_path.to_str().unwrap();
+                            text_diffs.lock().unwrap().push(format!(
+                                "{}",
+                                diff.unified_diff().header(path_string, path_string)
+                            ));
+                        }
+                        Err(e) => {
+                            let path = file_path.display().to_string();
+                            text_diffs.lock().unwrap().push(format!(
+                                "{}: the formatted output couldn't be formatted again: {}\n",
+                                path,
+                                describe_rubyfmt_error(&e)
+                            ));
+                        }
+                    }
+                }
+            });
+
+            let text_diffs = text_diffs.lock().unwrap();
+            for diff in text_diffs.iter() {
+                puts_stdout(diff);
+            }
+            puts_stdout(&format!(
+                "Checked {} files, {} weren't idempotent\n",
+                checked.lock().unwrap(),
+                text_diffs.len()
+            ));
+            report_profile(&opts);
+            if text_diffs.is_empty() {
+                exit(0)
+            } else {
+                exit(rubyfmt::FormatError::DiffDetected as i32);
+            }
+        }
+
         CommandlineOpts { check: true, .. } => {
-            initialize_rubyfmt();
+            initialize_rubyfmt(&opts);
             let text_diffs: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(Vec::new()));
 
             iterate_formatted(&opts, &|(file_path, before, after)| match after {
@@ -372,10 +981,14 @@ fn main() {
 
             for diff in all_diffs.iter() {
                 if !diff.is_empty() {
-                    puts_stdout(diff);
+                    if opts.report.is_none() {
+                        puts_stdout(diff);
+                    }
                     diffs_reported += 1
                 }
             }
+            print_report(&opts);
+            report_profile(&opts);
             if diffs_reported > 0 {
                 exit(rubyfmt::FormatError::DiffDetected as i32);
             } else {
@@ -384,7 +997,7 @@ fn main() {
         }
 
         CommandlineOpts { in_place: true, .. } => {
-            initialize_rubyfmt();
+            initialize_rubyfmt(&opts);
             iterate_formatted(&opts, &|(file_path, before, after)| match after {
                 None => {}
                 Some(fmtted) => {
@@ -408,14 +1021,22 @@ fn main() {
         }
 
         _ => {
-            initialize_rubyfmt();
-            iterate_formatted(&opts, &|(_, before, after)| match after {
-                Some(fmtted) => puts_stdout(&fmtted),
-                None => puts_stdout(before),
-            })
+            initialize_rubyfmt(&opts);
+            iterate_formatted(&opts, &|(_, before, after)| {
+                if opts.report.is_some() {
+                    return;
+                }
+                match after {
+                    Some(fmtted) => puts_stdout(&fmtted),
+                    None => puts_stdout(before),
+                }
+            });
         }
     }
 
+    print_report(&opts);
+    report_profile(&opts);
+
     if !opts.silence_update_message {
         updates::report_if_update_available();
     }